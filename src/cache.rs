@@ -1,10 +1,23 @@
 use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
 use std::sync::Mutex;
 use std::time::SystemTime;
 use lazy_static::lazy_static;
 
+pub type Heading = (u8, String, String);
+
+struct CacheEntry {
+    modified: SystemTime,
+    html: String,
+    headings: Vec<Heading>,
+    // Other files the page was rendered from (embedded notes), with their mtimes back then.
+    dependencies: Vec<(PathBuf, SystemTime)>,
+    math_fallback: bool,
+}
+
 pub struct MarkdownCache {
-    entries: HashMap<String, (SystemTime, String, Vec<(u8, String, String)>)>,
+    entries: HashMap<String, CacheEntry>,
 }
 
 impl MarkdownCache {
@@ -14,21 +27,52 @@ impl MarkdownCache {
         }
     }
 
-    pub fn get_if_fresh(&self, path: &str, current_modified: SystemTime) -> Option<(String, Vec<(u8, String, String)>)> {
-        self.entries.get(path).and_then(|(cached_modified, html, headings)| {
-            if *cached_modified == current_modified {
-                Some((html.clone(), headings.clone()))
+    /// The cached render of `path`, as long as neither the page nor anything it embeds
+    /// has changed since.
+    pub fn get_if_fresh(&self, path: &str, current_modified: SystemTime) -> Option<(String, Vec<Heading>, bool)> {
+        self.entries.get(path).and_then(|entry| {
+            let dependencies_fresh = entry.dependencies.iter().all(|(dependency, modified)| {
+                fs::metadata(dependency)
+                    .and_then(|metadata| metadata.modified())
+                    .is_ok_and(|current| current == *modified)
+            });
+            if entry.modified == current_modified && dependencies_fresh {
+                Some((entry.html.clone(), entry.headings.clone(), entry.math_fallback))
             } else {
                 None
             }
         })
     }
 
-    pub fn set(&mut self, path: String, modified: SystemTime, html: String, headings: Vec<(u8, String, String)>) {
-        self.entries.insert(path, (modified, html, headings));
+    pub fn set(
+        &mut self,
+        path: String,
+        modified: SystemTime,
+        html: String,
+        headings: Vec<Heading>,
+        dependencies: Vec<(PathBuf, SystemTime)>,
+        math_fallback: bool,
+    ) {
+        self.entries.insert(path, CacheEntry {
+            modified,
+            html,
+            headings,
+            dependencies,
+            math_fallback,
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Forgets the entry for `path` and, if it was a directory, every entry below it.
+    pub fn invalidate(&mut self, path: &str) {
+        let prefix = format!("{}/", path);
+        self.entries.retain(|key, _| key != path && !key.starts_with(&prefix));
     }
 }
 
 lazy_static! {
     pub static ref MARKDOWN_CACHE: Mutex<MarkdownCache> = Mutex::new(MarkdownCache::new());
-}
\ No newline at end of file
+}
@@ -0,0 +1,420 @@
+use chrono::NaiveDate;
+use lazy_static::lazy_static;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use crate::file_tree::{build_file_tree, FileNode};
+use crate::markdown::{collect_links, extract_frontmatter, NoteStyle};
+
+lazy_static! {
+    static ref DATE_REGEX: Regex = Regex::new(r"(\d{1,2})\s+(Jan(?:uary)?|Feb(?:ruary)?|Mar(?:ch)?|Apr(?:il)?|May|Jun(?:e)?|Jul(?:y)?|Aug(?:ust)?|Sep(?:tember)?|Oct(?:ober)?|Nov(?:ember)?|Dec(?:ember)?)\s+(\d{4})").unwrap();
+}
+
+/// Typed view of a page's frontmatter plus the URL it is served at.
+#[derive(Debug, Clone, Serialize)]
+pub struct Page {
+    pub title: String,
+    pub description: Option<String>,
+    pub date: Option<NaiveDate>,
+    pub draft: bool,
+    pub author: Option<String>,
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+    pub notes: NoteStyle,
+    pub url: String,
+}
+
+impl Page {
+    /// Directory part of the URL, e.g. `blog/tutorial` for `blog/tutorial/awesome-bar`.
+    pub fn section(&self) -> &str {
+        self.url.rsplit_once('/').map(|(dir, _)| dir).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ContentEntry {
+    pub page: Page,
+    pub source: PathBuf,
+    pub body: String,
+    /// Line of the source file the body starts on, counting from 1.
+    pub body_line: usize,
+}
+
+impl ContentEntry {
+    /// Line of the source file that byte `offset` of the body is on.
+    pub fn line(&self, offset: usize) -> usize {
+        self.body_line + self.body[..offset].matches('\n').count()
+    }
+}
+
+#[derive(Deserialize, Default)]
+struct Frontmatter {
+    title: Option<String>,
+    description: Option<String>,
+    date: Option<String>,
+    #[serde(default)]
+    draft: bool,
+    author: Option<String>,
+    category: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_tags")]
+    tags: Vec<String>,
+    #[serde(default)]
+    notes: NoteStyle,
+}
+
+// Tags may be written either as a YAML list or as a comma separated string.
+fn deserialize_tags<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Tags {
+        List(Vec<String>),
+        Single(String),
+    }
+
+    Ok(match Option::<Tags>::deserialize(deserializer)? {
+        Some(Tags::List(tags)) => tags,
+        Some(Tags::Single(tags)) => tags
+            .split(',')
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .collect(),
+        None => Vec::new(),
+    })
+}
+
+/// Every markdown file under `content/`, parsed once and keyed by URL.
+#[derive(Default)]
+pub struct ContentIndex {
+    base_path: PathBuf,
+    entries: BTreeMap<String, ContentEntry>,
+    tree: Vec<FileNode>,
+    // URL of each published page to the URLs of the pages it links to, and the reverse.
+    links: BTreeMap<String, Vec<String>>,
+    backlinks: HashMap<String, Vec<String>>,
+}
+
+pub static CONTENT_INDEX: Lazy<RwLock<ContentIndex>> = Lazy::new(|| RwLock::new(ContentIndex::default()));
+
+pub fn initialize_content_index(base_path: &Path) -> std::io::Result<()> {
+    let index = ContentIndex::build(base_path)?;
+    *CONTENT_INDEX.write().unwrap() = index;
+    Ok(())
+}
+
+impl ContentIndex {
+    pub fn build(base_path: &Path) -> std::io::Result<Self> {
+        let mut index = ContentIndex {
+            base_path: base_path.to_path_buf(),
+            entries: BTreeMap::new(),
+            tree: Vec::new(),
+            links: BTreeMap::new(),
+            backlinks: HashMap::new(),
+        };
+        index.load_directory(base_path)?;
+        index.tree = build_file_tree(index.published());
+        index.link_pages();
+        Ok(index)
+    }
+
+    pub fn get_by_source(&self, source: &Path) -> Option<&ContentEntry> {
+        let url = page_url(&self.base_path, source).ok()?;
+        self.entries.get(&url)
+    }
+
+    /// All pages, drafts included.
+    pub fn entries(&self) -> impl Iterator<Item = &ContentEntry> {
+        self.entries.values()
+    }
+
+    /// Pages that are not drafts: what the sidebar, search and RSS show.
+    pub fn published(&self) -> impl Iterator<Item = &ContentEntry> {
+        self.entries.values().filter(|entry| !entry.page.draft)
+    }
+
+    pub fn file_tree(&self) -> &[FileNode] {
+        &self.tree
+    }
+
+    /// Every published page with the URLs of the pages it links to.
+    pub fn links(&self) -> impl Iterator<Item = (&str, &[String])> {
+        self.links.iter().map(|(source, targets)| (source.as_str(), targets.as_slice()))
+    }
+
+    /// Published pages that link to the page at `url`, wikilinks and plain links alike.
+    pub fn backlinks(&self, url: &str) -> Vec<&Page> {
+        self.backlinks
+            .get(url)
+            .map(|sources| sources.iter().filter_map(|source| self.entries.get(source)).map(|entry| &entry.page).collect())
+            .unwrap_or_default()
+    }
+
+    /// Finds the page a `[[wikilink]]` points at: by URL, then by file name, then by title,
+    /// ignoring case for the last two.
+    pub fn resolve_wikilink(&self, target: &str) -> Option<&ContentEntry> {
+        let target = target.trim().trim_start_matches('/').trim_end_matches(".md");
+        if target.is_empty() {
+            return None;
+        }
+        if let Some(entry) = self.get(target) {
+            return Some(entry);
+        }
+
+        let target = target.to_lowercase();
+        self.entries
+            .values()
+            .find(|entry| page_stem(&entry.page.url).to_lowercase() == target)
+            .or_else(|| self.entries.values().find(|entry| entry.page.title.to_lowercase() == target))
+    }
+
+    /// Finds the page a regular markdown link on the page at `from` points at, if it is a
+    /// page on this site.
+    pub fn resolve_path(&self, from: &str, dest: &str) -> Option<&Page> {
+        let path = resolve_link_path(from, dest)?;
+        self.get(path.trim_end_matches(".md")).map(|entry| &entry.page)
+    }
+
+    /// The page at `url`; `dir/index.md` answers to both `dir/index` and `dir`.
+    pub fn get(&self, url: &str) -> Option<&ContentEntry> {
+        self.entries
+            .get(url)
+            .or_else(|| self.entries.get(&format!("{}/index", url)))
+    }
+
+    // Reads the links from the same `Link` events the renderer sees, but in a pass of its
+    // own: pages are only rendered when first requested, while backlinks and the graph
+    // need every page's links up front, and rendering everything (highlighting, math,
+    // diagrams) on each start and reload would be wasted work. An `![[embed]]` counts as a
+    // link to the embedded note; the links inside it belong to that note, not the page.
+    fn link_pages(&mut self) {
+        let mut links: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for entry in self.published() {
+            let targets = links.entry(entry.page.url.clone()).or_default();
+            for link in collect_links(&entry.body) {
+                if link.image && !link.wiki {
+                    continue;
+                }
+                let target = if link.wiki {
+                    link.dest
+                        .split('#')
+                        .next()
+                        .and_then(|target| self.resolve_wikilink(target))
+                        .map(|entry| &entry.page)
+                } else {
+                    self.resolve_path(&entry.page.url, &link.dest)
+                };
+
+                if let Some(target) = target
+                    && target.url != entry.page.url
+                    && !targets.contains(&target.url)
+                {
+                    targets.push(target.url.clone());
+                }
+            }
+        }
+
+        let mut backlinks: HashMap<String, Vec<String>> = HashMap::new();
+        for (source, targets) in &links {
+            for target in targets {
+                backlinks.entry(target.clone()).or_default().push(source.clone());
+            }
+        }
+        self.links = links;
+        self.backlinks = backlinks;
+    }
+
+    /// Re-reads `relative` (a file or a directory below the content root) from disk,
+    /// dropping whatever was indexed for it if it no longer exists.
+    pub fn refresh(&mut self, relative: &Path) {
+        let full_path = self.base_path.join(relative);
+        let url = relative_url(relative);
+
+        if full_path.is_dir() {
+            self.remove_directory(&url);
+            if let Err(e) = self.load_directory(&full_path) {
+                eprintln!("Failed to index {}: {}", full_path.display(), e);
+            }
+        } else if full_path.is_file() {
+            if let Err(e) = self.load_file(&full_path) {
+                eprintln!("Failed to index {}: {}", full_path.display(), e);
+            }
+        } else if relative.extension().is_some_and(|ext| ext == "md") {
+            self.entries.remove(&url);
+        } else {
+            self.remove_directory(&url);
+        }
+
+        self.tree = build_file_tree(self.published());
+        self.link_pages();
+    }
+
+    /// Everything a link can resolve by, to tell whether a change may have broken or fixed
+    /// links on other pages.
+    pub fn link_targets(&self) -> Vec<(String, String)> {
+        self.entries
+            .values()
+            .map(|entry| (entry.page.url.clone(), entry.page.title.clone()))
+            .collect()
+    }
+
+    fn remove_directory(&mut self, url: &str) {
+        let prefix = format!("{}/", url);
+        self.entries.retain(|key, _| !key.starts_with(&prefix));
+    }
+
+    fn load_directory(&mut self, dir: &Path) -> std::io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.file_name().is_some_and(|n| n.to_string_lossy().starts_with('.')) {
+                continue;
+            }
+
+            if path.is_dir() {
+                self.load_directory(&path)?;
+            } else if path.extension().is_some_and(|ext| ext == "md") {
+                self.load_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn load_file(&mut self, path: &Path) -> std::io::Result<()> {
+        if path.extension().is_none_or(|ext| ext != "md") {
+            return Ok(());
+        }
+
+        let raw_content = fs::read_to_string(path)?;
+        let (frontmatter, body) = extract_frontmatter(&raw_content);
+        let url = page_url(&self.base_path, path)?;
+        let page = parse_page(&frontmatter, path, url.clone());
+        let body_line = raw_content[..raw_content.len() - body.len()].matches('\n').count() + 1;
+
+        self.entries.insert(url, ContentEntry {
+            page,
+            source: path.to_path_buf(),
+            body: body.to_string(),
+            body_line,
+        });
+        Ok(())
+    }
+}
+
+/// The site path (without a leading slash, `#fragment` or `?query`) a markdown link on the
+/// page at `from` points at. Relative links resolve the way a browser would; links off the
+/// site and bare `#fragment` links give `None`.
+pub fn resolve_link_path(from: &str, dest: &str) -> Option<String> {
+    let has_scheme = dest.split_once(':').is_some_and(|(scheme, _)| !scheme.contains(['/', '#', '?']));
+    if has_scheme || dest.starts_with("//") || dest.starts_with('#') {
+        return None;
+    }
+    let path = dest.split(['#', '?']).next()?;
+
+    let mut segments: Vec<&str> = Vec::new();
+    if !path.starts_with('/') {
+        segments.extend(from.split('/'));
+        segments.pop();
+    }
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    Some(segments.join("/"))
+}
+
+// Name a page is known by in wikilinks: its file name, or its directory for `index.md`.
+fn page_stem(url: &str) -> &str {
+    let url = url.strip_suffix("/index").unwrap_or(url);
+    url.rsplit('/').next().unwrap_or(url)
+}
+
+fn page_url(base_path: &Path, path: &Path) -> std::io::Result<String> {
+    let rel_path = path
+        .strip_prefix(base_path)
+        .map_err(|_| std::io::Error::other("Failed to get relative path"))?;
+
+    Ok(relative_url(rel_path))
+}
+
+/// URL of a page or directory at `relative` below the content root. Only `.md` is dropped,
+/// so a directory like `notes/v1.2` keeps its name.
+pub fn relative_url(relative: &Path) -> String {
+    let path = if relative.extension().is_some_and(|ext| ext == "md") {
+        relative.with_extension("")
+    } else {
+        relative.to_path_buf()
+    };
+    path.to_string_lossy().replace('\\', "/")
+}
+
+fn parse_page(frontmatter: &JsonValue, path: &Path, url: String) -> Page {
+    let frontmatter: Frontmatter = match frontmatter {
+        JsonValue::Object(map) => serde_json::from_value(frontmatter.clone()).unwrap_or_else(|e| {
+            eprintln!("Invalid frontmatter in {}: {}", path.display(), e);
+            // Still honour `draft` so a typo elsewhere does not publish the page; anything
+            // other than a plain `false` keeps it hidden.
+            let draft = map.get("draft").is_some_and(|draft| draft.as_bool() != Some(false));
+            Frontmatter { draft, ..Frontmatter::default() }
+        }),
+        _ => Frontmatter::default(),
+    };
+
+    let title = frontmatter
+        .title
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .unwrap_or_else(|| path.file_stem().unwrap_or_default().to_string_lossy().to_string());
+
+    Page {
+        title,
+        description: frontmatter.description.map(|d| d.trim().to_string()),
+        date: frontmatter.date.as_deref().and_then(parse_date),
+        draft: frontmatter.draft,
+        author: frontmatter.author.map(|a| a.trim().to_string()),
+        category: frontmatter.category.map(|c| c.trim().to_string()),
+        tags: frontmatter.tags,
+        notes: frontmatter.notes,
+        url,
+    }
+}
+
+pub fn parse_date(date_str: &str) -> Option<NaiveDate> {
+    if let Ok(date) = NaiveDate::parse_from_str(date_str.trim(), "%Y-%m-%d") {
+        return Some(date);
+    }
+
+    if let Some(captures) = DATE_REGEX.captures(date_str) {
+        let day: u32 = captures.get(1)?.as_str().parse().ok()?;
+        let month_str = captures.get(2)?.as_str();
+        let year: i32 = captures.get(3)?.as_str().parse().ok()?;
+
+        let month = match &month_str.to_lowercase()[..3] {
+            "jan" => 1,
+            "feb" => 2,
+            "mar" => 3,
+            "apr" => 4,
+            "may" => 5,
+            "jun" => 6,
+            "jul" => 7,
+            "aug" => 8,
+            "sep" => 9,
+            "oct" => 10,
+            "nov" => 11,
+            "dec" => 12,
+            _ => return None,
+        };
+
+        NaiveDate::from_ymd_opt(year, month, day)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,160 @@
+use layout::backends::svg::SVGWriter;
+use layout::gv::{DotParser, GraphBuilder};
+use lazy_static::lazy_static;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+
+lazy_static! {
+    static ref MERMAID_HEADER_REGEX: Regex = Regex::new(r"^(?:graph|flowchart)(?:\s+(TD|TB|BT|LR|RL))?$").unwrap();
+    // `id`, optionally followed by a shape with its text: `[box]`, `(round)`, `((circle))`...
+    static ref MERMAID_NODE_REGEX: Regex = Regex::new(
+        r#"^([A-Za-z0-9_]+)(\(\(\(.*?\)\)\)|\(\(.*?\)\)|\(\[.*?\]\)|\[\[.*?\]\]|\[.*?\]|\(.*?\)|\{.*?\})?"#
+    ).unwrap();
+    // `-->`, `---`, `-.->`, `==>` and friends, with an optional `|label|`.
+    static ref MERMAID_EDGE_REGEX: Regex = Regex::new(r"^(-->|---|-\.->|-\.-|==>|===)(?:\|([^|]*)\|)?").unwrap();
+    // The same with the label inline: `-- label -->`.
+    static ref MERMAID_LABELLED_EDGE_REGEX: Regex = Regex::new(r"^(--|-\.|==)\s+(.+?)\s+(-->|---|\.->|\.-|==>|===)").unwrap();
+    static ref SVG_REFERENCE_REGEX: Regex = Regex::new(r##"(id="|url\(#|href="#|class=")([A-Za-z])"##).unwrap();
+    static ref SVG_STYLE_CLASS_REGEX: Regex = Regex::new(r"\.(a\d+) \{").unwrap();
+}
+
+/// Whether fenced blocks in `lang` are diagrams rather than code.
+pub fn is_diagram(lang: &str) -> bool {
+    matches!(lang, "dot" | "graphviz" | "mermaid")
+}
+
+/// The diagram in `source` as inline SVG, or `None` if it cannot be laid out, in which case
+/// it is best shown as code.
+pub fn render_diagram(lang: &str, source: &str) -> Option<String> {
+    let dot = match lang {
+        "dot" | "graphviz" => source.to_string(),
+        "mermaid" => mermaid_to_dot(source)?,
+        _ => return None,
+    };
+
+    // The layout code asserts rather than erroring on some graphs, an empty one for instance.
+    let svg = std::panic::catch_unwind(|| {
+        let graph = DotParser::new(&dot).process().ok()?;
+        let mut builder = GraphBuilder::new();
+        builder.visit_graph(&graph);
+        let mut visual = builder.get();
+        let mut writer = SVGWriter::new();
+        visual.do_it(false, false, false, &mut writer);
+        Some(writer.finalize())
+    })
+    .ok()??;
+
+    Some(scope_svg(&svg, source))
+}
+
+// The SVG is meant to be a file of its own: it starts with an XML declaration and uses the
+// same ids and style classes every time, which would clash with other diagrams on the page.
+fn scope_svg(svg: &str, source: &str) -> String {
+    let digest = Sha256::digest(source.as_bytes());
+    let prefix = format!("d{:02x}{:02x}{:02x}{:02x}", digest[0], digest[1], digest[2], digest[3]);
+
+    let svg = svg.split_once("?>").map(|(_, rest)| rest).unwrap_or(svg);
+    let svg = SVG_REFERENCE_REGEX.replace_all(svg, format!("${{1}}{}-${{2}}", prefix).as_str());
+    let svg = SVG_STYLE_CLASS_REGEX.replace_all(&svg, format!(".{}-${{1}} {{", prefix).as_str());
+    svg.replace("font-family: Times, serif;", "font-family: inherit;")
+}
+
+// Translates the flowchart part of mermaid (`graph LR`, nodes, chained edges with labels)
+// into dot. Anything else gives `None` rather than a diagram missing parts.
+fn mermaid_to_dot(source: &str) -> Option<String> {
+    let mut lines = source
+        .lines()
+        .flat_map(|line| line.split(';'))
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("%%"));
+
+    let header = MERMAID_HEADER_REGEX.captures(lines.next()?)?;
+    let mut dot = String::from("digraph {\nnode [shape=box];\n");
+    if matches!(header.get(1).map(|m| m.as_str()), Some("LR" | "RL")) {
+        dot.push_str("rankdir=LR;\n");
+    }
+
+    for line in lines {
+        let (mut from, rest) = mermaid_node(line, &mut dot)?;
+        let mut rest = rest.trim_start();
+        while !rest.is_empty() {
+            let (edge, after_edge) = mermaid_edge(rest)?;
+            let (to, after_node) = mermaid_node(after_edge.trim_start(), &mut dot)?;
+            dot.push_str(&format!("{} {} {}{};\n", quote(from), edge.0, quote(to), edge.1));
+            from = to;
+            rest = after_node.trim_start();
+        }
+    }
+    dot.push_str("}\n");
+    Some(dot)
+}
+
+// Reads a node reference off the front of `input`, declaring it in `dot` if it comes with a
+// shape. Returns the node id and what follows it.
+fn mermaid_node<'a>(input: &'a str, dot: &mut String) -> Option<(&'a str, &'a str)> {
+    let captures = MERMAID_NODE_REGEX.captures(input)?;
+    let id = captures.get(1)?.as_str();
+    if let Some(shape) = captures.get(2) {
+        let shape = shape.as_str();
+        let depth = shape.chars().take_while(|c| matches!(c, '(' | '[' | '{')).count();
+        let text = unquote(shape[depth..shape.len() - depth].trim());
+        let kind = match (shape.starts_with('('), depth) {
+            (true, 2) => "circle",
+            (true, 3) => "doublecircle",
+            _ => "box",
+        };
+        dot.push_str(&format!("{} [shape={}, label={}];\n", quote(id), kind, quote(text)));
+    }
+    Some((id, &input[captures.get(0)?.end()..]))
+}
+
+// Reads an edge off the front of `input`: the dot edge operator, its attributes and what
+// follows it.
+fn mermaid_edge(input: &str) -> Option<((&'static str, String), &str)> {
+    let (arrow, label, end) = if let Some(captures) = MERMAID_EDGE_REGEX.captures(input) {
+        let label = captures.get(2).map(|m| m.as_str().trim());
+        (captures.get(1)?.as_str(), label, captures.get(0)?.end())
+    } else {
+        let captures = MERMAID_LABELLED_EDGE_REGEX.captures(input)?;
+        (captures.get(3)?.as_str(), captures.get(2).map(|m| m.as_str()), captures.get(0)?.end())
+    };
+
+    let operator = if arrow.ends_with('>') { "->" } else { "--" };
+    let mut attributes = Vec::new();
+    if let Some(label) = label.filter(|label| !label.is_empty()) {
+        attributes.push(format!("label={}", quote(unquote(label))));
+    }
+    if arrow.contains('.') {
+        attributes.push("style=dashed".to_string());
+    }
+    if arrow.starts_with('=') {
+        attributes.push("penwidth=2".to_string());
+    }
+    let attributes = if attributes.is_empty() {
+        String::new()
+    } else {
+        format!(" [{}]", attributes.join(", "))
+    };
+    Some(((operator, attributes), &input[end..]))
+}
+
+// `"text"` as `text`. Only one surrounding pair goes, a quote that ends the text stays.
+fn unquote(text: &str) -> &str {
+    text.strip_prefix('"').and_then(|text| text.strip_suffix('"')).unwrap_or(text)
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mermaid_labels_lose_only_their_surrounding_quotes() {
+        let dot = mermaid_to_dot("graph TD\nA[\"say \"hi\"\"] -->|\"a \"b\"\"| B").unwrap();
+        assert!(dot.contains(r#"label="say \"hi\"""#), "{}", dot);
+        assert!(dot.contains(r#"label="a \"b\"""#), "{}", dot);
+    }
+}
@@ -0,0 +1,120 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::content::CONTENT_INDEX;
+use crate::handlers::{
+    content_og_details, render_index, render_markdown_page, render_not_found, render_projects, render_static_search_page,
+    WEB_OG_PAGES,
+};
+use crate::highlight::{highlight_css, theme_names};
+use crate::image_generator::{generate_content_og_image, generate_web_og_image};
+use crate::rss::build_rss_feed;
+use crate::search::serialize_search_index;
+use crate::state::AppState;
+
+/// Renders every route the server knows about into `out_dir`, laid out so that a plain
+/// static host serves the same URLs: `/blog/nixos` becomes `blog/nixos/index.html`. `/search`
+/// answers queries in the browser from the exported `search-index.json`.
+pub async fn export_site(app_state: &AppState, out_dir: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(out_dir)?;
+
+    write_page(out_dir, "", &render_index(app_state, "/").map_err(to_io)?)?;
+    write_page(out_dir, "stuff", &render_projects(app_state, "/stuff").map_err(to_io)?)?;
+    write_page(out_dir, "search", &render_static_search_page(app_state, "/search").map_err(to_io)?)?;
+    fs::write(out_dir.join("404.html"), render_not_found(app_state, "/404").map_err(to_io)?)?;
+    fs::write(out_dir.join("rss.xml"), build_rss_feed().map_err(to_io)?)?;
+    fs::write(out_dir.join("search-index.json"), serialize_search_index()?)?;
+
+    // `/resume` streams the PDF from the server; a static host can only point at it.
+    write_page(
+        out_dir,
+        "resume",
+        r#"<!DOCTYPE html><meta http-equiv="refresh" content="0; url=/static/pdfs/resume.pdf">"#,
+    )?;
+
+    let avatar_lock = app_state.avatar.read().await;
+    let avatar = avatar_lock.as_ref().cloned();
+    drop(avatar_lock);
+
+    let pages = collect_pages();
+
+    for (url, file_path) in &pages {
+        let (html, _) = render_markdown_page(app_state, file_path, url, &format!("/{}", url))
+            .map_err(to_io)?;
+        write_page(out_dir, url, &html)?;
+
+        let (title, dir_path) = content_og_details(file_path);
+        let image_bytes = generate_content_og_image(
+            &title,
+            &dir_path,
+            &app_state.title_font,
+            &app_state.path_font,
+            &avatar,
+        );
+        write_file(&out_dir.join("og/content").join(format!("{}.png", url)), &image_bytes)?;
+    }
+
+    for (name, title, subtitle) in WEB_OG_PAGES {
+        let image_bytes = generate_web_og_image(
+            title,
+            subtitle,
+            &app_state.title_font,
+            &app_state.path_font,
+            &avatar,
+        );
+        write_file(&out_dir.join("og/web").join(format!("{}.png", name)), &image_bytes)?;
+    }
+
+    copy_dir(Path::new("static"), &out_dir.join("static"))?;
+    for theme in theme_names() {
+        let css = highlight_css(&theme)?;
+        write_file(&out_dir.join("static").join(format!("highlight-{}.css", theme)), css.as_bytes())?;
+    }
+
+    println!("Exported {} pages to {}", pages.len() + 3, out_dir.display());
+    Ok(())
+}
+
+fn to_io(e: actix_web::Error) -> std::io::Error {
+    std::io::Error::other(e.to_string())
+}
+
+// Every published page, keyed by the URL path the server would serve it at. Drafts are left
+// out, as they are from the sidebar and the feed. `dir/index.md` is reachable both as
+// `dir/index` and as `dir`.
+fn collect_pages() -> Vec<(String, PathBuf)> {
+    let content = CONTENT_INDEX.read().unwrap();
+    let mut pages = Vec::new();
+    for entry in content.published() {
+        pages.push((entry.page.url.clone(), entry.source.clone()));
+        if let Some(dir_url) = entry.page.url.strip_suffix("/index") {
+            pages.push((dir_url.to_string(), entry.source.clone()));
+        }
+    }
+    pages
+}
+
+fn write_page(out_dir: &Path, url: &str, html: &str) -> std::io::Result<()> {
+    write_file(&out_dir.join(url).join("index.html"), html.as_bytes())
+}
+
+fn write_file(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,380 @@
+use futures_util::stream::{self, StreamExt};
+use reqwest::{redirect::Policy, Client, StatusCode, Url};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::{sleep_until, Instant};
+
+use crate::content::{ContentIndex, CONTENT_INDEX};
+use crate::markdown::collect_links;
+use crate::projects::get_projects;
+
+const MAX_REDIRECTS: usize = 10;
+const USER_AGENT: &str = concat!("personal-link-checker/", env!("CARGO_PKG_VERSION"));
+
+/// Where an external URL is linked from.
+#[derive(Debug, Clone, Serialize)]
+pub struct LinkSource {
+    pub file: String,
+    pub line: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum LinkStatus {
+    Ok,
+    Redirected { code: u16, location: String },
+    Dead { code: u16 },
+    Error { message: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LinkReport {
+    pub url: String,
+    #[serde(flatten)]
+    pub status: LinkStatus,
+    pub sources: Vec<LinkSource>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CheckOptions {
+    /// Requests in flight at once.
+    pub concurrency: usize,
+    /// Minimum time between two requests to the same host.
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+impl Default for CheckOptions {
+    fn default() -> Self {
+        CheckOptions {
+            concurrency: 8,
+            interval: Duration::from_millis(500),
+            timeout: Duration::from_secs(15),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Markdown,
+    Json,
+}
+
+/// Every http(s) URL linked from a page, drafts included, or from the projects list,
+/// with everywhere it appears.
+pub fn collect_external_links(content: &ContentIndex) -> BTreeMap<String, Vec<LinkSource>> {
+    let mut links: BTreeMap<String, Vec<LinkSource>> = BTreeMap::new();
+    for entry in content.entries() {
+        for link in collect_links(&entry.body) {
+            if link.dest.starts_with("http://") || link.dest.starts_with("https://") {
+                links.entry(link.dest).or_default().push(LinkSource {
+                    file: entry.source.to_string_lossy().replace('\\', "/"),
+                    line: Some(entry.line(link.offset)),
+                });
+            }
+        }
+    }
+    for project in get_projects().into_iter().flat_map(|category| category.array) {
+        if let Some(link) = project.link {
+            links.entry(link).or_default().push(LinkSource {
+                file: format!("src/projects.rs ({})", project.name),
+                line: None,
+            });
+        }
+    }
+    links
+}
+
+/// The client `check_external_links` expects: it must not follow redirects itself, so that
+/// they can be reported.
+pub fn build_client(options: &CheckOptions) -> reqwest::Result<Client> {
+    Client::builder()
+        .redirect(Policy::none())
+        .timeout(options.timeout)
+        .connect_timeout(options.timeout)
+        .user_agent(USER_AGENT)
+        .build()
+}
+
+/// Probes every URL in `links` and reports how each one answered, in the order given.
+pub async fn check_external_links(
+    client: &Client,
+    links: BTreeMap<String, Vec<LinkSource>>,
+    options: &CheckOptions,
+) -> Vec<LinkReport> {
+    let limiter = HostLimiter::new(options.interval);
+    let limiter = &limiter;
+
+    let mut reports: Vec<(usize, LinkReport)> = stream::iter(links.into_iter().enumerate())
+        .map(|(i, (url, sources))| async move {
+            let status = probe(client, limiter, &url).await;
+            (i, LinkReport { url, status, sources })
+        })
+        .buffer_unordered(options.concurrency.max(1))
+        .collect()
+        .await;
+
+    reports.sort_by_key(|(i, _)| *i);
+    reports.into_iter().map(|(_, report)| report).collect()
+}
+
+// Follows redirects by hand so that a link which ends up somewhere else is reported as
+// such, along with where it ends up.
+async fn probe(client: &Client, limiter: &HostLimiter, url: &str) -> LinkStatus {
+    let mut current = match Url::parse(url) {
+        Ok(url) => url,
+        Err(e) => return LinkStatus::Error { message: e.to_string() },
+    };
+    let mut first_redirect = None;
+
+    for _ in 0..=MAX_REDIRECTS {
+        let status = match request(client, limiter, &current).await {
+            Ok(status) => status,
+            Err(e) => return LinkStatus::Error { message: e },
+        };
+
+        if status.0.is_redirection() {
+            let Some(next) = status.1.and_then(|location| current.join(&location).ok()) else {
+                return LinkStatus::Error { message: format!("{} without a location", status.0) };
+            };
+            first_redirect.get_or_insert(status.0.as_u16());
+            current = next;
+            continue;
+        }
+
+        return match (status.0.is_success(), first_redirect) {
+            (true, None) => LinkStatus::Ok,
+            (true, Some(code)) => LinkStatus::Redirected { code, location: current.to_string() },
+            (false, _) => LinkStatus::Dead { code: status.0.as_u16() },
+        };
+    }
+    LinkStatus::Error { message: "too many redirects".to_string() }
+}
+
+// Status and `Location` header for `url`. HEAD first, falling back to GET for servers that
+// do not answer HEAD properly.
+async fn request(client: &Client, limiter: &HostLimiter, url: &Url) -> Result<(StatusCode, Option<String>), String> {
+    let mut response = None;
+    for method in [reqwest::Method::HEAD, reqwest::Method::GET] {
+        limiter.wait(url).await;
+        let answer = client
+            .request(method, url.clone())
+            .send()
+            .await
+            .map_err(|e| describe_error(&e))?;
+
+        let retry = matches!(
+            answer.status(),
+            StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED | StatusCode::FORBIDDEN | StatusCode::NOT_FOUND
+        );
+        response = Some(answer);
+        if !retry {
+            break;
+        }
+    }
+
+    let response = response.expect("at least one request is made");
+    let location = response
+        .headers()
+        .get(reqwest::header::LOCATION)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    Ok((response.status(), location))
+}
+
+fn describe_error(error: &reqwest::Error) -> String {
+    if error.is_timeout() {
+        "timed out".to_string()
+    } else if error.is_connect() {
+        "could not connect".to_string()
+    } else {
+        error.to_string()
+    }
+}
+
+// Spaces out requests to the same host so a page full of GitHub links does not get the
+// checker rate limited.
+struct HostLimiter {
+    interval: Duration,
+    next: Mutex<HashMap<String, Instant>>,
+}
+
+impl HostLimiter {
+    fn new(interval: Duration) -> Self {
+        HostLimiter { interval, next: Mutex::new(HashMap::new()) }
+    }
+
+    async fn wait(&self, url: &Url) {
+        let host = url.host_str().unwrap_or_default().to_string();
+        let slot = {
+            let mut next = self.next.lock().await;
+            let now = Instant::now();
+            let slot = next.get(&host).copied().filter(|slot| *slot > now).unwrap_or(now);
+            next.insert(host, slot + self.interval);
+            slot
+        };
+        sleep_until(slot).await;
+    }
+}
+
+/// Renders the links that did not simply answer with a success.
+pub fn render_report(reports: &[LinkReport], format: ReportFormat) -> String {
+    let problems: Vec<&LinkReport> = reports.iter().filter(|report| !matches!(report.status, LinkStatus::Ok)).collect();
+    match format {
+        ReportFormat::Json => serde_json::to_string_pretty(&serde_json::json!({
+            "checked": reports.len(),
+            "problems": problems,
+        }))
+        .unwrap_or_default(),
+        ReportFormat::Markdown => render_markdown(reports.len(), &problems),
+    }
+}
+
+fn render_markdown(checked: usize, problems: &[&LinkReport]) -> String {
+    let mut out = String::from("# external links\n\n");
+    let count = |f: fn(&LinkStatus) -> bool| problems.iter().filter(|report| f(&report.status)).count();
+    let dead = count(|status| matches!(status, LinkStatus::Dead { .. } | LinkStatus::Error { .. }));
+    let redirected = count(|status| matches!(status, LinkStatus::Redirected { .. }));
+    out.push_str(&format!("checked {} urls: {} dead, {} redirected\n", checked, dead, redirected));
+
+    for (heading, redirects) in [("dead", false), ("redirected", true)] {
+        let section: Vec<&&LinkReport> = problems
+            .iter()
+            .filter(|report| matches!(report.status, LinkStatus::Redirected { .. }) == redirects)
+            .collect();
+        if section.is_empty() {
+            continue;
+        }
+
+        out.push_str(&format!("\n## {}\n\n", heading));
+        for report in section {
+            let status = match &report.status {
+                LinkStatus::Ok => String::new(),
+                LinkStatus::Redirected { code, location } => format!("{} → <{}>", code, location),
+                LinkStatus::Dead { code } => code.to_string(),
+                LinkStatus::Error { message } => message.clone(),
+            };
+            out.push_str(&format!("- <{}> ({})\n", report.url, status));
+            for source in &report.sources {
+                match source.line {
+                    Some(line) => out.push_str(&format!("  - {}:{}\n", source.file, line)),
+                    None => out.push_str(&format!("  - {}\n", source.file)),
+                }
+            }
+        }
+    }
+    out
+}
+
+/// `check-links [--format markdown|json] [--output FILE] [--concurrency N] [--timeout SECS]
+/// [--interval MS]`: checks every external link and writes the report to stdout or FILE.
+/// Returns how many links are dead, so the caller can fail and gate a deploy.
+pub async fn check_links_command(args: &[String]) -> std::io::Result<usize> {
+    let invalid = |message: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, message);
+    let mut options = CheckOptions::default();
+    let mut format = ReportFormat::Markdown;
+    let mut output = None;
+
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or_else(|| invalid(format!("{} needs a value", flag)))?;
+        let number = || value.parse::<u64>().map_err(|_| invalid(format!("{} expects a number, got {}", flag, value)));
+        match flag.as_str() {
+            "--format" => {
+                format = match value.as_str() {
+                    "markdown" | "md" => ReportFormat::Markdown,
+                    "json" => ReportFormat::Json,
+                    _ => return Err(invalid(format!("unknown report format {}", value))),
+                }
+            }
+            "--output" => output = Some(value.clone()),
+            "--concurrency" => options.concurrency = number()? as usize,
+            "--timeout" => options.timeout = Duration::from_secs(number()?),
+            "--interval" => options.interval = Duration::from_millis(number()?),
+            _ => return Err(invalid(format!("unknown option {}", flag))),
+        }
+    }
+
+    let links = collect_external_links(&CONTENT_INDEX.read().unwrap());
+    eprintln!("Checking {} external links", links.len());
+    let client = build_client(&options).map_err(std::io::Error::other)?;
+    let reports = check_external_links(&client, links, &options).await;
+
+    let report = render_report(&reports, format);
+    match output {
+        Some(path) => std::fs::write(path, report)?,
+        None => println!("{}", report),
+    }
+
+    let dead = reports
+        .iter()
+        .filter(|report| matches!(report.status, LinkStatus::Dead { .. } | LinkStatus::Error { .. }))
+        .count();
+    if dead > 0 {
+        eprintln!("Found {} dead external links", dead);
+    }
+    Ok(dead)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // Answers every request by its path: `/ok` with 200, `/old` with a redirect to `/older`,
+    // which redirects on to `/ok`, and anything else with 404.
+    async fn serve() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        actix_rt::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buffer = vec![0; 4096];
+                let read = stream.read(&mut buffer).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buffer[..read]);
+                let path = request.split_whitespace().nth(1).unwrap_or("/");
+                let (status, location) = match path {
+                    "/ok" => ("200 OK", None),
+                    "/old" => ("301 Moved Permanently", Some("/older")),
+                    "/older" => ("301 Moved Permanently", Some("/ok")),
+                    _ => ("404 Not Found", None),
+                };
+                let location = location.map(|to| format!("Location: {}\r\n", to)).unwrap_or_default();
+                let response = format!("HTTP/1.1 {}\r\n{}Content-Length: 0\r\nConnection: close\r\n\r\n", status, location);
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        format!("http://{}", address)
+    }
+
+    #[actix_rt::test]
+    async fn reports_ok_redirected_and_dead_links() {
+        let base = serve().await;
+        let options = CheckOptions { interval: Duration::ZERO, timeout: Duration::from_secs(5), ..CheckOptions::default() };
+        let source = || vec![LinkSource { file: "content/test.md".to_string(), line: Some(1) }];
+        let links = BTreeMap::from([
+            (format!("{}/ok", base), source()),
+            (format!("{}/old", base), source()),
+            (format!("{}/missing", base), source()),
+        ]);
+
+        let client = build_client(&options).unwrap();
+        let reports = check_external_links(&client, links, &options).await;
+        let status = |path: &str| &reports.iter().find(|report| report.url.ends_with(path)).unwrap().status;
+
+        assert!(matches!(status("/ok"), LinkStatus::Ok));
+        assert!(matches!(status("/missing"), LinkStatus::Dead { code: 404 }));
+        match status("/old") {
+            LinkStatus::Redirected { code, location } => {
+                assert_eq!(*code, 301);
+                assert_eq!(*location, format!("{}/ok", base));
+            }
+            other => panic!("expected a redirect, got {:?}", other),
+        }
+
+        let report = render_report(&reports, ReportFormat::Markdown);
+        assert!(report.contains("checked 3 urls: 1 dead, 1 redirected"));
+    }
+}
@@ -1,9 +1,6 @@
 use serde::{Deserialize, Serialize};
-use std::fs;
-use std::path::Path;
-use std::io::{BufRead, BufReader};
-use std::fs::File;
-use std::sync::Arc;
+
+use crate::content::{ContentEntry, CONTENT_INDEX};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FileNode {
@@ -13,101 +10,69 @@ pub struct FileNode {
     pub children: Vec<FileNode>,
 }
 
-pub fn build_file_tree(base: &Path, relative: &Path) -> Vec<FileNode> {
-    let full_path = base.join(relative);
+/// Builds the sidebar tree out of the given pages, creating a directory node for every
+/// path segment along the way.
+pub fn build_file_tree<'a>(entries: impl Iterator<Item = &'a ContentEntry>) -> Vec<FileNode> {
     let mut nodes = Vec::new();
 
-    if let Ok(entries) = fs::read_dir(&full_path) {
-        for entry in entries.filter_map(|e| e.ok()) {
-            let path = entry.path();
-            let file_name = path
-                .file_name()
-                .unwrap_or_default()
-                .to_string_lossy()
-                .to_string();
+    for entry in entries {
+        let segments: Vec<&str> = entry.page.url.split('/').collect();
+        let (file_segment, dir_segments) = match segments.split_last() {
+            Some(split) => split,
+            None => continue,
+        };
 
-            if file_name.starts_with('.') {
-                continue;
+        let mut current = &mut nodes;
+        let mut dir_path = String::new();
+        for segment in dir_segments {
+            if !dir_path.is_empty() {
+                dir_path.push('/');
             }
+            dir_path.push_str(segment);
 
-            let is_dir = path.is_dir();
-            let rel_path = relative.join(&file_name);
-            let path_str = rel_path.to_string_lossy().replace('\\', "/");
-
-            if is_dir {
-                let children = build_file_tree(base, &rel_path);
-                nodes.push(FileNode {
-                    name: file_name,
-                    path: path_str,
-                    is_dir,
-                    children,
-                });
-            } else if path.extension().map_or(false, |ext| ext == "md") {
-                let mut name = String::new();
-                let default_name = path
-                    .file_stem()
-                    .unwrap_or_default()
-                    .to_string_lossy()
-                    .to_string();
-
-                if let Ok(file) = File::open(&path) {
-                    let reader = BufReader::new(file);
-                    let mut in_frontmatter = false;
-                    let mut found_title = false;
-
-                    for line in reader.lines().filter_map(Result::ok) {
-                        let trimmed_line = line.trim();
-                        if trimmed_line == "---" {
-                            if in_frontmatter {
-                                break;
-                            } else {
-                                in_frontmatter = true;
-                                continue;
-                            }
-                        }
-                        if in_frontmatter {
-                            if let Some((key, value)) = trimmed_line.split_once(':') {
-                                let key = key.trim();
-                                if key == "title" {
-                                    name = value.trim().to_string();
-                                    found_title = true;
-                                    break;
-                                }
-                            }
-                        }
-                    }
-                    if !found_title || name.is_empty() {
-                        name = default_name.clone();
-                    }
-                } else {
-                    name = default_name.clone();
+            let index = match current.iter().position(|n: &FileNode| n.is_dir && n.path == dir_path) {
+                Some(index) => index,
+                None => {
+                    current.push(FileNode {
+                        name: segment.to_string(),
+                        path: dir_path.clone(),
+                        is_dir: true,
+                        children: Vec::new(),
+                    });
+                    current.len() - 1
                 }
-
-                let trimmed_path = if path_str.ends_with(".md") {
-                    path_str[..path_str.len() - 3].to_string()
-                } else {
-                    path_str.clone()
-                };
-
-                nodes.push(FileNode {
-                    name,
-                    path: trimmed_path,
-                    is_dir,
-                    children: Vec::new(),
-                });
-            }
+            };
+            current = &mut current[index].children;
         }
+
+        let name = if entry.page.title.is_empty() {
+            file_segment.to_string()
+        } else {
+            entry.page.title.clone()
+        };
+        current.push(FileNode {
+            name,
+            path: entry.page.url.clone(),
+            is_dir: false,
+            children: Vec::new(),
+        });
     }
 
+    sort_nodes(&mut nodes);
+    nodes
+}
+
+fn sort_nodes(nodes: &mut [FileNode]) {
     nodes.sort_by(|a, b| match (a.is_dir, b.is_dir) {
         (true, false) => std::cmp::Ordering::Less,
         (false, true) => std::cmp::Ordering::Greater,
         _ => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
     });
-
-    nodes
+    for node in nodes.iter_mut() {
+        sort_nodes(&mut node.children);
+    }
 }
 
-pub fn get_file_tree(file_tree: &Arc<Vec<FileNode>>) -> Vec<FileNode> {
-    file_tree.as_ref().clone()
-}
\ No newline at end of file
+pub fn get_file_tree() -> Vec<FileNode> {
+    CONTENT_INDEX.read().unwrap().file_tree().to_vec()
+}
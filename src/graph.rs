@@ -0,0 +1,104 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::content::{ContentIndex, CONTENT_INDEX};
+
+pub const DEFAULT_DEPTH: usize = 1;
+pub const MAX_DEPTH: usize = 4;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphNode {
+    pub id: String,
+    pub title: String,
+    pub section: String,
+    pub category: Option<String>,
+    /// Number of links to and from the page within the graph.
+    pub degree: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphEdge {
+    pub source: String,
+    pub target: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Graph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+/// Every published page and every link between two of them.
+pub fn build_graph() -> Graph {
+    let content = CONTENT_INDEX.read().unwrap();
+    graph_of(&content, |_| true)
+}
+
+/// The pages within `depth` links of the page at `url`, following links in either
+/// direction, and the links between them. `None` if there is no such published page.
+pub fn neighbourhood(url: &str, depth: usize) -> Option<Graph> {
+    let content = CONTENT_INDEX.read().unwrap();
+    let start = content.get(url).filter(|entry| !entry.page.draft)?.page.url.as_str();
+
+    let mut neighbours: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (source, targets) in content.links() {
+        for target in targets {
+            neighbours.entry(source).or_default().push(target);
+            neighbours.entry(target).or_default().push(source);
+        }
+    }
+
+    let mut included: HashSet<&str> = HashSet::from([start]);
+    let mut queue = VecDeque::from([(start, 0)]);
+    while let Some((url, distance)) = queue.pop_front() {
+        if distance == depth {
+            continue;
+        }
+        for &next in neighbours.get(url).into_iter().flatten() {
+            if included.insert(next) {
+                queue.push_back((next, distance + 1));
+            }
+        }
+    }
+
+    Some(graph_of(&content, |url| included.contains(url)))
+}
+
+fn graph_of(content: &ContentIndex, include: impl Fn(&str) -> bool) -> Graph {
+    let pages: HashSet<&str> = content
+        .published()
+        .map(|entry| entry.page.url.as_str())
+        .filter(|url| include(url))
+        .collect();
+
+    // Links to drafts or to pages left out of the graph have nothing to point at.
+    let edges: Vec<GraphEdge> = content
+        .links()
+        .flat_map(|(source, targets)| targets.iter().map(move |target| (source, target.as_str())))
+        .filter(|(source, target)| pages.contains(source) && pages.contains(target))
+        .map(|(source, target)| GraphEdge {
+            source: source.to_string(),
+            target: target.to_string(),
+        })
+        .collect();
+
+    let mut degrees: HashMap<&str, usize> = HashMap::new();
+    for edge in &edges {
+        *degrees.entry(&edge.source).or_default() += 1;
+        *degrees.entry(&edge.target).or_default() += 1;
+    }
+
+    let nodes = content
+        .published()
+        .filter(|entry| pages.contains(entry.page.url.as_str()))
+        .map(|entry| GraphNode {
+            id: entry.page.url.clone(),
+            title: entry.page.title.clone(),
+            section: entry.page.section().to_string(),
+            category: entry.page.category.clone(),
+            degree: degrees.get(entry.page.url.as_str()).copied().unwrap_or(0),
+        })
+        .collect();
+
+    Graph { nodes, edges }
+}
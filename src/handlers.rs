@@ -1,33 +1,55 @@
 use actix_web::{web, HttpRequest, HttpResponse, Responder, Result};
 use crate::state::AppState;
 use crate::file_tree::get_file_tree;
-use crate::markdown::{markdown_to_html, extract_frontmatter};
+use crate::markdown::{markdown_to_html, extract_frontmatter, find_unknown_languages, MarkdownOptions};
 use crate::cache::MARKDOWN_CACHE;
+use crate::content::CONTENT_INDEX;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 use tera::Context;
 use serde_json::Value as JsonValue;
 use std::collections::HashMap;
 use crate::image_generator::{generate_content_og_image, generate_web_og_image};
 use crate::tweet::generate_tweet;
 use serde::Deserialize;
-use crate::search::search_content;
+use crate::search::{
+    search_content, serialize_search_index, suggest, SearchOptions, SearchPage, DEFAULT_LIMIT, MAX_CONTEXTS, MAX_LIMIT, MAX_SNIPPET_WINDOW,
+};
 use crate::projects::get_projects;
+use crate::graph::{build_graph, neighbourhood, DEFAULT_DEPTH, MAX_DEPTH};
+use crate::linkcheck::BROKEN_LINKS;
+use crate::highlight::highlight_css;
 use serde::Serialize;
 
+pub fn render_index(app_state: &AppState, path: &str) -> Result<String, actix_web::Error> {
+    let file_tree = get_file_tree();
+    let mut context = Context::new();
+    context.insert("file_tree", &file_tree);
+    context.insert("path", path);
+    app_state.tera
+        .render("index.html", &context)
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Template error"))
+}
+
 pub async fn index(
     app_state: web::Data<AppState>,
     _: web::Query<HashMap<String, String>>,
     req: HttpRequest,  
 ) -> Result<HttpResponse, actix_web::Error> {
-    let file_tree = get_file_tree(&app_state.file_tree);
+    let html = render_index(&app_state, req.path())?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(html))
+}
+
+pub fn render_projects(app_state: &AppState, path: &str) -> Result<String, actix_web::Error> {
+    let file_tree = get_file_tree();
     let mut context = Context::new();
     context.insert("file_tree", &file_tree);
-    context.insert("path", &req.path());
-    let html = app_state.tera
-        .render("index.html", &context)
-        .map_err(|_| actix_web::error::ErrorInternalServerError("Template error"))?;
-    Ok(HttpResponse::Ok().content_type("text/html").body(html))
+    context.insert("projects", &get_projects());
+    context.insert("path", path);
+    app_state.tera
+        .render("projects.html", &context)
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Template error"))
 }
 
 pub async fn projects(
@@ -35,14 +57,7 @@ pub async fn projects(
     _: web::Query<HashMap<String, String>>,
     req: HttpRequest,  
 ) -> Result<HttpResponse, actix_web::Error> {
-    let file_tree = get_file_tree(&app_state.file_tree);
-    let mut context = Context::new();
-    context.insert("file_tree", &file_tree);
-    context.insert("projects", &get_projects());
-    context.insert("path", &req.path());
-    let html = app_state.tera
-        .render("projects.html", &context)
-        .map_err(|_| actix_web::error::ErrorInternalServerError("Template error"))?;
+    let html = render_projects(&app_state, req.path())?;
     Ok(HttpResponse::Ok()
         .insert_header((actix_web::http::header::CACHE_CONTROL, "public, max-age=60"))
         .content_type("text/html")
@@ -54,32 +69,90 @@ pub async fn projects(
 pub struct SearchQuery {
     #[serde(default)]
     q: String,
+    // Characters of context around each hit, capped at `MAX_SNIPPET_WINDOW`.
+    window: Option<usize>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+    max_contexts: Option<usize>,
+    // 1-based page number, used by the HTML search page instead of `offset`.
+    page: Option<usize>,
 }
 
+impl SearchQuery {
+    fn options(&self) -> SearchOptions {
+        let defaults = SearchOptions::default();
+        SearchOptions {
+            limit: self.limit.unwrap_or(defaults.limit).clamp(1, MAX_LIMIT),
+            offset: self.offset.unwrap_or(defaults.offset),
+            max_contexts: self.max_contexts.unwrap_or(defaults.max_contexts).min(MAX_CONTEXTS),
+            window: self.window.unwrap_or(defaults.window).min(MAX_SNIPPET_WINDOW),
+        }
+    }
+}
 
-pub async fn search_page(
-    app_state: web::Data<AppState>,
-    query: web::Query<SearchQuery>,
-    request : HttpRequest,
-) -> Result<HttpResponse, actix_web::Error> {
-    let file_tree = get_file_tree(&app_state.file_tree);
+
+pub fn render_search_page(app_state: &AppState, path: &str, query: &str, page: usize) -> Result<String, actix_web::Error> {
+    let file_tree = get_file_tree();
     let mut context = Context::new();
     
     context.insert("file_tree", &file_tree);
-    context.insert("path", &request.path());
+    context.insert("path", path);
     
-    if !query.q.is_empty() {
-        let results = search_content(&query.q);
-        context.insert("results", &results);
-        context.insert("query", &query.q);
+    if !query.is_empty() {
+        let (results, page, pages) = search_results_page(page, |options| search_content(query, options));
+        context.insert("results", &results.results);
+        context.insert("total", &results.total);
+        context.insert("page", &page);
+        context.insert("pages", &pages);
+        context.insert("first", &(results.offset + 1));
+        context.insert("last", &(results.offset + results.results.len()));
+        context.insert("query", query);
         context.insert("has_query", &true);
     } else {
         context.insert("has_query", &false);
     }
     
-    let html = app_state.tera
+    app_state.tera
         .render("search.html", &context)
-        .map_err(|_| actix_web::error::ErrorInternalServerError("Template error"))?;
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Template error"))
+}
+
+/// The search page for static exports: no results are rendered, `static/search.js` answers
+/// the query in the browser from `/search-index.json`.
+pub fn render_static_search_page(app_state: &AppState, path: &str) -> Result<String, actix_web::Error> {
+    let mut context = Context::new();
+    context.insert("file_tree", &get_file_tree());
+    context.insert("path", path);
+    context.insert("has_query", &false);
+    context.insert("client_search", &true);
+
+    app_state.tera
+        .render("search.html", &context)
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Template error"))
+}
+
+// Page `page` of the results `search` finds, clamped to the pages there are, along with the
+// page number actually shown and the number of pages.
+fn search_results_page(page: usize, search: impl Fn(SearchOptions) -> SearchPage) -> (SearchPage, usize, usize) {
+    let offset = |page: usize| page.saturating_sub(1).saturating_mul(DEFAULT_LIMIT);
+    let page = page.max(1);
+    let results = search(SearchOptions { offset: offset(page), ..SearchOptions::default() });
+    let pages = results.total.div_ceil(DEFAULT_LIMIT);
+
+    let last = pages.max(1);
+    if page > last {
+        let results = search(SearchOptions { offset: offset(last), ..SearchOptions::default() });
+        return (results, last, pages);
+    }
+    (results, page, pages)
+}
+
+pub async fn search_page(
+    app_state: web::Data<AppState>,
+    query: web::Query<SearchQuery>,
+    request : HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let html = render_search_page(&app_state, request.path(), &query.q, query.page.unwrap_or(1))?;
     
     Ok(HttpResponse::Ok()
         .insert_header((actix_web::http::header::CACHE_CONTROL, "public, max-age=60"))
@@ -87,81 +160,99 @@ pub async fn search_page(
         .body(html))
 }
 
-pub async fn view_markdown(
-    app_state: web::Data<AppState>,
-    path: web::Path<(String,)>,
-    req : HttpRequest
-) -> Result<HttpResponse, actix_web::Error> {
-    let path_param = &path.0;
-    let base_path = PathBuf::from("content");
-    let mut file_path = base_path.join(path_param);
-
-    let file_tree = get_file_tree(&app_state.file_tree);
-
-    if !file_path.is_file() {
-        let md_path = file_path.with_extension("md");
-        if md_path.is_file() {
-            file_path = md_path;
-        } else if file_path.is_dir() {
-            let index_path = file_path.join("index.md");
-            if index_path.is_file() {
-                file_path = index_path;
-            } else {
-                let mut context = Context::new();
-                context.insert("file_tree", &file_tree);
-                context.insert("path", &req.path());
-                let html = app_state.tera
-                    .render("404.html", &context)
-                    .map_err(|e| {
-                        eprintln!("Tera error: {:?}", e);
-                        actix_web::error::ErrorInternalServerError("Template rendering failed")
-                    })?;
-                return Ok(HttpResponse::NotFound().content_type("text/html").body(html));
-            }
-        } else {
-            let mut context = Context::new();
-            context.insert("path", &req.path());
-            context.insert("file_tree", &file_tree);
-            let html = app_state.tera
-                .render("404.html", &context)
-                .map_err(|e| {
-                    eprintln!("Tera error: {:?}", e);
-                    actix_web::error::ErrorInternalServerError("Template rendering failed")
-                })?;
-            return Ok(HttpResponse::NotFound().content_type("text/html").body(html));
-        }
+/// Maps a request path onto the markdown file that serves it: the file itself,
+/// `<path>.md`, or `<path>/index.md`.
+pub fn resolve_content_path(path_param: &str) -> Option<PathBuf> {
+    let file_path = PathBuf::from("content").join(path_param);
+    if file_path.is_file() {
+        return Some(file_path);
     }
+    let md_path = file_path.with_extension("md");
+    if md_path.is_file() {
+        return Some(md_path);
+    }
+    let index_path = file_path.join("index.md");
+    if file_path.is_dir() && index_path.is_file() {
+        return Some(index_path);
+    }
+    None
+}
+
+pub fn render_not_found(app_state: &AppState, path: &str) -> Result<String, actix_web::Error> {
+    let file_tree = get_file_tree();
+    let mut context = Context::new();
+    context.insert("file_tree", &file_tree);
+    context.insert("path", path);
+    app_state.tera
+        .render("404.html", &context)
+        .map_err(|e| {
+            eprintln!("Tera error: {:?}", e);
+            actix_web::error::ErrorInternalServerError("Template rendering failed")
+        })
+}
+
+/// Renders a markdown page through the cache and `view.html`, returning the page
+/// along with the source file's modification time.
+pub fn render_markdown_page(
+    app_state: &AppState,
+    file_path: &Path,
+    path_param: &str,
+    path: &str,
+) -> Result<(String, SystemTime), actix_web::Error> {
+    let file_tree = get_file_tree();
+    let (title, options, backlinks) = {
+        let content = CONTENT_INDEX.read().unwrap();
+        match content.get_by_source(file_path) {
+            Some(entry) => (
+                entry.page.title.clone(),
+                MarkdownOptions {
+                    notes: entry.page.notes,
+                    url: Some(entry.page.url.clone()),
+                },
+                content.backlinks(&entry.page.url).into_iter().cloned().collect(),
+            ),
+            None => ("Untitled".to_string(), MarkdownOptions::default(), Vec::new()),
+        }
+    };
 
     let cache_key = file_path.to_string_lossy().to_string();
-    let current_modified = fs::metadata(&file_path)
+    let current_modified = fs::metadata(file_path)
         .map_err(|_| actix_web::error::ErrorInternalServerError("Could not get file metadata"))?
         .modified()
         .map_err(|_| actix_web::error::ErrorInternalServerError("Could not get last modified time"))?;
     let mut cache = MARKDOWN_CACHE.lock().unwrap();
-    let (content_html, headings, frontmatter) = if let Some((html, headings)) = cache.get_if_fresh(&cache_key, current_modified) {
-        let raw_content = fs::read_to_string(&file_path)
+    let (content_html, headings, math_fallback, frontmatter) = if let Some((html, headings, math_fallback)) = cache.get_if_fresh(&cache_key, current_modified) {
+        let raw_content = fs::read_to_string(file_path)
             .map_err(|_| actix_web::error::ErrorInternalServerError("Could not read file"))?;
         let (frontmatter, _) = extract_frontmatter(&raw_content);
-        (html, headings, frontmatter)
+        (html, headings, math_fallback, frontmatter)
     } else {
-        let raw_content = fs::read_to_string(&file_path)
+        let raw_content = fs::read_to_string(file_path)
             .map_err(|_| actix_web::error::ErrorInternalServerError("Could not read file"))?;
         let (frontmatter, body) = extract_frontmatter(&raw_content);
-        let (content_html, headings) = markdown_to_html(body, &app_state.highlighter);
-        cache.set(cache_key.clone(), current_modified, content_html.clone(), headings.clone());
-        (content_html, headings, frontmatter)
+        let rendered = markdown_to_html(body, &app_state.highlighter, &options);
+        cache.set(
+            cache_key.clone(),
+            current_modified,
+            rendered.html.clone(),
+            rendered.headings.clone(),
+            rendered.dependencies,
+            rendered.math_fallback,
+        );
+        (rendered.html, rendered.headings, rendered.math_fallback, frontmatter)
     };
+    drop(cache);
 
     let processed_frontmatter = if let JsonValue::Object(mut map) = frontmatter {
         if !map.contains_key("title") {
             eprintln!("Missing title in frontmatter for {}", path_param);
-            map.insert("title".to_string(), JsonValue::String("Untitled".to_string()));
         }
+        map.insert("title".to_string(), JsonValue::String(title));
         JsonValue::Object(map)
     } else {
         JsonValue::Object({
             let mut map = serde_json::Map::new();
-            map.insert("title".to_string(), JsonValue::String("Untitled".to_string()));
+            map.insert("title".to_string(), JsonValue::String(title));
             map
         })
     };
@@ -173,15 +264,33 @@ pub async fn view_markdown(
         }
     }
     context.insert("headings", &headings);
+    context.insert("backlinks", &backlinks);
     context.insert("file_tree", &file_tree);
     context.insert("content", &content_html);
-    context.insert("file_path", &path_param);
-    context.insert("path", &req.path());
+    context.insert("math_fallback", &math_fallback);
+    context.insert("file_path", path_param);
+    context.insert("path", path);
 
     let html = app_state.tera
         .render("view.html", &context)
         .map_err(|_| actix_web::error::ErrorInternalServerError("Template error"))?;
 
+    Ok((html, current_modified))
+}
+
+pub async fn view_markdown(
+    app_state: web::Data<AppState>,
+    path: web::Path<(String,)>,
+    req : HttpRequest
+) -> Result<HttpResponse, actix_web::Error> {
+    let path_param = &path.0;
+    let Some(file_path) = resolve_content_path(path_param) else {
+        let html = render_not_found(&app_state, req.path())?;
+        return Ok(HttpResponse::NotFound().content_type("text/html").body(html));
+    };
+
+    let (html, current_modified) = render_markdown_page(&app_state, &file_path, path_param, req.path())?;
+
     let last_modified_header = actix_web::http::header::LastModified(current_modified.into());
     Ok(HttpResponse::Ok()
         .insert_header((actix_web::http::header::CACHE_CONTROL, "public, max-age=0"))
@@ -195,7 +304,7 @@ pub async fn generate_tweet_image(
     path: web::Path<(String,)>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let title_font = &*app_state.title_font;
-    let path_font: &ab_glyph::FontRef<'_> = &*app_state.path_font;
+    let path_font: &ab_glyph::FontRef<'_> = &app_state.path_font;
     let id = &path.0;
 
     let image_bytes = generate_tweet(id, title_font, path_font).await.expect("Failed to generate tweet image");
@@ -220,61 +329,125 @@ pub async fn search(
 ) -> Result<HttpResponse, actix_web::Error> {
     let search_term = &query.q;
     
-    let results = search_content(search_term);
+    let results = search_content(search_term, query.options());
     
     Ok(HttpResponse::Ok()
         .content_type("application/json")
         .json(results))
 }
 
+pub async fn search_suggest(
+    query: web::Query<SearchQuery>,
+) -> Result<HttpResponse, actix_web::Error> {
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .json(suggest(&query.q)))
+}
 
+#[derive(Deserialize)]
+pub struct GraphQuery {
+    depth: Option<usize>,
+}
 
-pub async fn generate_og_image(
-    app_state: web::Data<AppState>,
+pub async fn graph() -> Result<HttpResponse, actix_web::Error> {
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .json(build_graph()))
+}
+
+pub async fn page_graph(
     path: web::Path<(String,)>,
+    query: web::Query<GraphQuery>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let path_param = &path.0;
-    let base_path = PathBuf::from("content");
-    let mut file_path = base_path.join(path_param);
-
-    if !file_path.is_file() {
-        let md_path = file_path.with_extension("md");
-        if md_path.is_file() {
-            file_path = md_path;
-        } else if file_path.is_dir() {
-            let index_path = file_path.join("index.md");
-            if index_path.is_file() {
-                file_path = index_path;
-            } else {
-                return Ok(HttpResponse::NotFound().body("Content not found"));
-            }
-        } else {
-            return Ok(HttpResponse::NotFound().body("Content not found"));
-        }
-    }
+    let depth = query.depth.unwrap_or(DEFAULT_DEPTH).min(MAX_DEPTH);
+    let graph = neighbourhood(path.0.trim_matches('/'), depth)
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Page not found"))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .json(graph))
+}
+
+/// Broken internal links across all pages, as of the last check at startup or after a
+/// content change.
+pub async fn broken_links() -> Result<HttpResponse, actix_web::Error> {
+    let broken = BROKEN_LINKS.read().unwrap().clone();
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .insert_header((actix_web::http::header::CACHE_CONTROL, "no-store"))
+        .json(serde_json::json!({ "count": broken.len(), "broken": broken })))
+}
 
-    let raw_content = std::fs::read_to_string(&file_path)
-        .map_err(|_| actix_web::error::ErrorInternalServerError("Could not read file"))?;
-    let title = if let Some(caps) = crate::markdown::FRONTMATTER_REGEX.captures(&raw_content) {
-        let yaml_str = caps.get(1).unwrap().as_str();
-        if let Ok(yaml) = serde_yaml::from_str::<JsonValue>(yaml_str) {
-            if let Some(title) = yaml.get("title") {
-                title.as_str().unwrap_or(&file_path.file_stem().unwrap_or_default().to_string_lossy()).to_string()
-            } else {
-                file_path.file_stem().unwrap_or_default().to_string_lossy().to_string()
-            }
+/// Code blocks tagged with a language that has no grammar.
+pub async fn unknown_languages() -> Result<HttpResponse, actix_web::Error> {
+    let unknown = web::block(find_unknown_languages)
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Language check failed"))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .insert_header((actix_web::http::header::CACHE_CONTROL, "no-store"))
+        .json(serde_json::json!({ "count": unknown.len(), "blocks": unknown })))
+}
+
+pub async fn highlight_stylesheet(theme: web::Path<(String,)>) -> Result<HttpResponse, actix_web::Error> {
+    let css = highlight_css(&theme.0).map_err(|e| {
+        if e.kind() == std::io::ErrorKind::NotFound {
+            actix_web::error::ErrorNotFound("Theme not found")
         } else {
-            file_path.file_stem().unwrap_or_default().to_string_lossy().to_string()
+            eprintln!("Failed to load highlight theme {}: {}", theme.0, e);
+            actix_web::error::ErrorInternalServerError("Invalid theme")
         }
-    } else {
-        file_path.file_stem().unwrap_or_default().to_string_lossy().to_string()
-    };
+    })?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/css")
+        .body(css))
+}
+
+pub async fn search_index_json() -> Result<HttpResponse, actix_web::Error> {
+    let json = serialize_search_index()
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to serialize search index"))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .insert_header((actix_web::http::header::CACHE_CONTROL, "public, max-age=60"))
+        .body(json))
+}
+
+
+
+/// Title and section directory shown on a content page's OG image.
+pub fn content_og_details(file_path: &Path) -> (String, String) {
+    let base_path = PathBuf::from("content");
+    let title = CONTENT_INDEX
+        .read()
+        .unwrap()
+        .get_by_source(file_path)
+        .map(|entry| entry.page.title.clone())
+        .unwrap_or_else(|| file_path.file_stem().unwrap_or_default().to_string_lossy().to_string());
     let dir_path = file_path
         .parent()
         .and_then(|p| p.strip_prefix(&base_path).ok())
         .map(|p| p.to_string_lossy().to_string())
         .unwrap_or_else(|| String::from("/"));
 
+    (title, dir_path)
+}
+
+pub async fn generate_og_image(
+    app_state: web::Data<AppState>,
+    path: web::Path<(String,)>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // `.png` is optional, static exports need it to serve the right content type.
+    let path = path.0.strip_suffix(".png").unwrap_or(&path.0);
+    let Some(file_path) = resolve_content_path(path) else {
+        return Ok(HttpResponse::NotFound().body("Content not found"));
+    };
+
+    let (title, dir_path) = content_og_details(&file_path);
+
     let title_font = &*app_state.title_font;
     let path_font = &*app_state.path_font;
     let avatar_lock = app_state.avatar.read().await;
@@ -288,17 +461,21 @@ pub async fn generate_og_image(
         .body(image_bytes))
 }
 
+/// Pages that have a pre-generated OG image under `/og/web/`, with their title and subtitle.
+pub const WEB_OG_PAGES: [(&str, &str, &str); 4] = [
+    ("index", "namishh", "personal website and garden"),
+    ("search", "namishh", "search stuff around here"),
+    ("stuff", "namishh", "stuff i have built"),
+    ("kino", "namishh", "list of personal resources"),
+];
+
 pub async fn generate_web_og(
     app_state: web::Data<AppState>,
     path: web::Path<(String,)>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let path_segment = &path.0;
-    let (title, subtitle) = match path_segment.as_str() {
-        "index" => ("namishh", "personal website and garden"),
-        "search" => ("namishh", "search stuff around here"),
-        "stuff" => ("namishh", "stuff i have built"),
-        "kino" => ("namishh", "list of personal resources"),
-        _ => return Ok(HttpResponse::NotFound().body("Invalid web path")),
+    let path_segment = path.0.strip_suffix(".png").unwrap_or(&path.0);
+    let Some((_, title, subtitle)) = WEB_OG_PAGES.iter().find(|(name, _, _)| *name == path_segment) else {
+        return Ok(HttpResponse::NotFound().body("Invalid web path"));
     };
 
     let title_font = &*app_state.title_font;
@@ -326,4 +503,51 @@ pub async fn health_check() -> impl Responder {
         status: "ok".to_string(),
         commit: commit_hash,
     })
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::content::ContentIndex;
+    use crate::search::{search_index, SearchIndex};
+
+    // A search index over `pages` pages that all mention rust, built from a temporary
+    // content directory named after the test, so the global indexes are left alone.
+    fn seeded_index(name: &str, pages: usize) -> SearchIndex {
+        let dir = std::env::temp_dir().join(format!("personal-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for n in 1..=pages {
+            fs::write(dir.join(format!("page-{}.md", n)), format!("---\ntitle: Page {}\n---\nNotes on rust.\n", n)).unwrap();
+        }
+        let content = ContentIndex::build(&dir);
+        let _ = fs::remove_dir_all(&dir);
+        SearchIndex::build(&content.unwrap())
+    }
+
+    // (total, offset, results shown, page shown, pages) for page `page` of `query`.
+    fn results_page(index: &SearchIndex, query: &str, page: usize) -> (usize, usize, usize, usize, usize) {
+        let (results, page, pages) = search_results_page(page, |options| search_index(index, query, options));
+        (results.total, results.offset, results.results.len(), page, pages)
+    }
+
+    #[test]
+    fn search_results_are_split_into_pages() {
+        let index = seeded_index("search-results-are-split-into-pages", 25);
+        assert_eq!(results_page(&index, "rust", 1), (25, 0, 10, 1, 3));
+        assert_eq!(results_page(&index, "rust", 2), (25, 10, 10, 2, 3));
+        assert_eq!(results_page(&index, "rust", 3), (25, 20, 5, 3, 3));
+    }
+
+    #[test]
+    fn search_page_zero_is_the_first_page() {
+        let index = seeded_index("search-page-zero-is-the-first-page", 25);
+        assert_eq!(results_page(&index, "rust", 0), (25, 0, 10, 1, 3));
+    }
+
+    #[test]
+    fn search_page_past_the_end_is_clamped() {
+        let index = seeded_index("search-page-past-the-end-is-clamped", 25);
+        assert_eq!(results_page(&index, "rust", 4), (25, 20, 5, 3, 3));
+        assert_eq!(results_page(&index, "rust", u64::MAX as usize), (25, 20, 5, 3, 3));
+        assert_eq!(results_page(&index, "zig", u64::MAX as usize), (0, 0, 0, 1, 0));
+    }
+}
@@ -0,0 +1,103 @@
+use inkjet::constants::HIGHLIGHT_NAMES;
+use inkjet::theme::{Modifier, Style, Theme};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const THEMES_DIR: &str = "themes";
+
+/// Stylesheet for the highlighting theme `name`, served at `/static/highlight-{name}.css`.
+///
+/// A theme is a pair of Helix theme files, `themes/{name}/light.toml` and
+/// `themes/{name}/dark.toml`, or a single `themes/{name}.toml` used in both modes. If only
+/// one of the pair exists it is used in both modes too.
+pub fn highlight_css(name: &str) -> io::Result<String> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("no theme called {}", name)));
+    }
+
+    let dir = Path::new(THEMES_DIR).join(name);
+    let (light, dark) = match (dir.join("light.toml"), dir.join("dark.toml")) {
+        (light, dark) if light.is_file() && dark.is_file() => (light, dark),
+        (light, _) if light.is_file() => (light.clone(), light),
+        (_, dark) if dark.is_file() => (dark.clone(), dark),
+        _ => {
+            let file = Path::new(THEMES_DIR).join(format!("{}.toml", name));
+            (file.clone(), file)
+        }
+    };
+
+    let mut css = format!("/* Generated from {} and {} */\n", light.display(), dark.display());
+    css.push_str(&theme_css(&load_theme(&light)?, ".code-block"));
+    css.push_str(&theme_css(&load_theme(&dark)?, "[data-theme=dark] .code-block"));
+    Ok(css)
+}
+
+/// Names of every theme in `themes/`, for the static export.
+pub fn theme_names() -> Vec<String> {
+    let Ok(entries) = fs::read_dir(THEMES_DIR) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_dir() || path.extension().is_some_and(|ext| ext == "toml"))
+        .filter_map(|path| path.file_stem().map(|stem| stem.to_string_lossy().to_string()))
+        .collect();
+    names.sort();
+    names
+}
+
+fn load_theme(path: &Path) -> io::Result<Theme> {
+    let data = fs::read_to_string(path)?;
+    Theme::from_helix(&data)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))
+}
+
+// One rule per highlight name. inkjet gives `keyword.control` the classes `keyword control`,
+// so a name styled the same as its parent is already covered by the parent's rule.
+fn theme_css(theme: &Theme, scope: &str) -> String {
+    let mut declarations: HashMap<&str, String> = HashMap::new();
+    let mut css = String::new();
+
+    for name in HIGHLIGHT_NAMES {
+        let Some(style) = theme.get_style(name) else {
+            continue;
+        };
+        let rule = style_declarations(style);
+        let parent = name.rsplit_once('.').and_then(|(parent, _)| declarations.get(parent));
+        if rule.is_empty() || parent == Some(&rule) {
+            declarations.insert(name, rule);
+            continue;
+        }
+
+        css.push_str(&format!("{} .{} {{ {} }}\n", scope, name, rule));
+        declarations.insert(name, rule);
+    }
+    css
+}
+
+fn style_declarations(style: &Style) -> String {
+    let mut rule = Vec::new();
+    if let Some(fg) = style.fg {
+        rule.push(format!("color: {};", fg.into_hex()));
+    }
+    if let Some(bg) = style.bg {
+        rule.push(format!("background-color: {};", bg.into_hex()));
+    }
+    if style.modifiers.contains(&Modifier::Bold) {
+        rule.push("font-weight: 600;".to_string());
+    }
+    if style.modifiers.contains(&Modifier::Italic) {
+        rule.push("font-style: italic;".to_string());
+    }
+    if style.modifiers.contains(&Modifier::Dim) {
+        rule.push("opacity: 0.7;".to_string());
+    }
+    if style.modifiers.contains(&Modifier::Strikethrough) {
+        rule.push("text-decoration: line-through;".to_string());
+    } else if style.underline.is_some() || style.modifiers.contains(&Modifier::Underlined) {
+        rule.push("text-decoration: underline;".to_string());
+    }
+    rule.join(" ")
+}
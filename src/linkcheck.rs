@@ -0,0 +1,121 @@
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::path::Path;
+use std::sync::RwLock;
+
+use crate::content::{resolve_link_path, ContentEntry, ContentIndex, CONTENT_INDEX};
+use crate::handlers::resolve_content_path;
+use crate::markdown::{collect_links, extract_section};
+use crate::routes::is_route;
+
+/// Result of the last check, kept so a reload only warns about links it newly broke.
+pub static BROKEN_LINKS: Lazy<RwLock<Vec<BrokenLink>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BrokenLink {
+    pub file: String,
+    pub line: usize,
+    pub target: String,
+    pub reason: String,
+}
+
+/// Checks every internal link on every page, drafts included, against the content tree
+/// and `static/`.
+pub fn check_links(content: &ContentIndex) -> Vec<BrokenLink> {
+    let mut broken = Vec::new();
+    for entry in content.entries() {
+        for link in collect_links(&entry.body) {
+            let Some(reason) = check_link(content, entry, &link.dest, link.wiki) else {
+                continue;
+            };
+            broken.push(BrokenLink {
+                file: entry.source.to_string_lossy().replace('\\', "/"),
+                line: entry.line(link.offset),
+                target: link.dest,
+                reason,
+            });
+        }
+    }
+    broken
+}
+
+// Why `dest` on `entry` leads nowhere, if it does.
+fn check_link(content: &ContentIndex, entry: &ContentEntry, dest: &str, wiki: bool) -> Option<String> {
+    if wiki {
+        let (target, fragment) = dest.split_once('#').unwrap_or((dest, ""));
+        let page = if target.trim().is_empty() {
+            Some(entry)
+        } else {
+            content.resolve_wikilink(target)
+        };
+        return match page {
+            Some(page) => check_fragment(page, fragment),
+            // `![[diagram.png]]` is an image next to the page rather than a page.
+            None if Path::new(target).extension().is_some_and(|ext| ext != "md") => {
+                check_link(content, entry, target, false)
+            }
+            None => Some(format!("no page called {}", target.trim())),
+        };
+    }
+
+    if let Some(fragment) = dest.strip_prefix('#') {
+        return check_fragment(entry, fragment);
+    }
+    let path = resolve_link_path(&entry.page.url, dest)?;
+    let fragment = dest.split_once('#').map(|(_, fragment)| fragment).unwrap_or_default();
+
+    if let Some(page) = content.get(path.trim_end_matches(".md")) {
+        return check_fragment(page, fragment);
+    }
+    if is_route(&path) {
+        return None;
+    }
+    if path.starts_with("static/") {
+        return (!Path::new(&path).is_file()).then(|| "no such file in static/".to_string());
+    }
+    if resolve_content_path(&path).is_some() {
+        return None;
+    }
+    Some("no such page or file".to_string())
+}
+
+fn check_fragment(page: &ContentEntry, fragment: &str) -> Option<String> {
+    // Footnote and code line anchors are generated while rendering, only headings are checked.
+    if fragment.is_empty()
+        || fragment.starts_with("fn-")
+        || fragment.starts_with("fnref-")
+        || fragment.starts_with("code-")
+        || extract_section(&page.body, fragment).is_some()
+    {
+        return None;
+    }
+    Some(format!("no heading #{} in {}", fragment, page.page.url))
+}
+
+/// Runs the check and warns about every broken link; called once at startup.
+pub fn initialize_link_check() {
+    let broken = check_links(&CONTENT_INDEX.read().unwrap());
+    for link in &broken {
+        warn(link);
+    }
+    if !broken.is_empty() {
+        eprintln!("Found {} broken internal links", broken.len());
+    }
+    *BROKEN_LINKS.write().unwrap() = broken;
+}
+
+/// Re-runs the check after content changed, warning only about links that were not
+/// already broken.
+pub fn recheck_links() -> Vec<BrokenLink> {
+    let broken = check_links(&CONTENT_INDEX.read().unwrap());
+    let mut previous = BROKEN_LINKS.write().unwrap();
+    for link in broken.iter().filter(|link| !previous.contains(link)) {
+        warn(link);
+    }
+    *previous = broken.clone();
+    broken
+}
+
+fn warn(link: &BrokenLink) {
+    eprintln!("Broken link in {}:{}: {} ({})", link.file, link.line, link.target, link.reason);
+}
@@ -1,22 +1,27 @@
 use actix_web::{web, App, HttpServer, middleware};
 use std::sync::{Arc, Mutex};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use ab_glyph::FontRef;
 use tokio::sync::RwLock;
 use std::time::Duration;
 use tokio::time;
-use reqwest;
-use image::load_from_memory;
+use image::{load_from_memory, DynamicImage};
 
 use crate::state::AppState;
-use crate::file_tree::build_file_tree;
-use crate::handlers::{index, projects, search_page, view_markdown, resume, generate_og_image, generate_web_og, generate_tweet_image, search, health_check};
+use crate::content::initialize_content_index;
+use crate::handlers::view_markdown;
+use crate::routes::{register_admin_routes, register_routes};
 use crate::templates::init_tera;
-use crate::rss::rss_feed;
 use crate::search::initialize_search_index;
+use crate::linkcheck::initialize_link_check;
+use crate::markdown::report_unknown_languages;
+use crate::external_links::check_links_command;
 use crate::middle::CacheControlMiddleware;
+use crate::watcher::watch_content;
+use crate::export::export_site;
 
 mod state;
+mod content;
 mod image_generator;
 mod file_tree;
 mod markdown;
@@ -28,16 +33,33 @@ mod tweet;
 mod search;
 mod middle;
 mod projects;
+mod watcher;
+mod export;
+mod graph;
+mod linkcheck;
+mod external_links;
+mod diagram;
+mod highlight;
+mod routes;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     let highlighter = Arc::new(Mutex::new(inkjet::Highlighter::new()));
 
     let base_path = Path::new("content");
-    let initial_tree = build_file_tree(base_path, Path::new(""));
-    let file_tree = Arc::new(initial_tree);
+    initialize_content_index(base_path)?;
 
-    initialize_search_index(base_path)?;
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("check-links") {
+        if check_links_command(&args[2..]).await? > 0 {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    initialize_search_index();
+    initialize_link_check();
+    report_unknown_languages();
 
     let title_font_data: &'static [u8] = include_bytes!("../static/_priv/fonts/InterE.ttf");
     let title_font = FontRef::try_from_slice(title_font_data).expect("Error loading title font");
@@ -50,20 +72,37 @@ async fn main() -> std::io::Result<()> {
     let avatar = Arc::new(RwLock::new(None));
     let avatar_for_closure = avatar.clone();
 
-    let mut address = "127.0.0.1:8080";
-    if let Ok(arg) = std::env::var("ENVIRONMENT") {
-        if arg == "PRODUCTION" {
-            address = "0.0.0.0:8080";
-        }
+    if args.get(1).map(String::as_str) == Some("build") {
+        let out_dir = args.get(2).map(PathBuf::from).unwrap_or_else(|| PathBuf::from("dist"));
+        *avatar.write().await = fetch_avatar().await;
+
+        let app_state = AppState {
+            tera: init_tera(),
+            highlighter,
+            title_font: title_font_arc,
+            path_font: path_font_arc,
+            avatar,
+        };
+        return export_site(&app_state, &out_dir).await;
     }
 
+    let _content_watcher = match watch_content(base_path) {
+        Ok(watcher) => Some(watcher),
+        Err(e) => {
+            eprintln!("Could not watch content directory, live reloading disabled: {}", e);
+            None
+        }
+    };
+
+    let production = std::env::var("ENVIRONMENT").is_ok_and(|arg| arg == "PRODUCTION");
+    let address = if production { "0.0.0.0:8080" } else { "127.0.0.1:8080" };
+
     let server = HttpServer::new(move || {
         let tera = init_tera();
 
         let app_state = AppState {
             tera,
             highlighter: highlighter.clone(),
-            file_tree: file_tree.clone(),
             title_font: title_font_arc.clone(),
             path_font: path_font_arc.clone(),
             avatar: avatar.clone(),
@@ -73,40 +112,23 @@ async fn main() -> std::io::Result<()> {
             .app_data(web::Data::new(app_state))
             .wrap(middleware::Logger::default())
             .wrap(CacheControlMiddleware)
+            .configure(register_routes)
+            .configure(|cfg| {
+                if !production {
+                    register_admin_routes(cfg);
+                }
+            })
             .service(
                 actix_files::Files::new("/static", "./static")
                     .use_last_modified(true)
                     .use_etag(true)
             )
-            .service(web::resource("/").route(web::get().to(index)))
-            .service(web::resource("/stuff").route(web::get().to(projects)))
-            .service(web::resource("/resume").route(web::get().to(resume)))
-            .service(web::resource("/search").route(web::get().to(search_page)))
-            .service(web::resource("/og/content/{path:.*}").route(web::get().to(generate_og_image)))
-            .service(web::resource("/og/web/{path:.*}").route(web::get().to(generate_web_og)))
-            .service(web::resource("/tweet/{path:.*}").route(web::get().to(generate_tweet_image)))
-            .service(web::resource("/rss.xml").route(web::get().to(rss_feed))) 
-            .service(web::resource("/api/search").route(web::get().to(search))) 
-            .service(web::resource("/health").route(web::get().to(health_check)))
             .service(web::resource("/{path:.*}").route(web::get().to(view_markdown)))
     })
     .bind(address)?
     .run();
 
     actix_rt::spawn(async move {
-        let fetch_avatar = || async {
-            if let Ok(response) = reqwest::get("https://github.com/namishh.png").await {
-                if response.status().is_success() {
-                    if let Ok(bytes) = response.bytes().await {
-                        if let Ok(img) = load_from_memory(&bytes) {
-                            return Some(img);
-                        }
-                    }
-                }
-            }
-            None
-        };
-
         if let Some(img) = fetch_avatar().await {
             let mut avatar_lock = avatar_for_closure.write().await;
             *avatar_lock = Some(img);
@@ -123,4 +145,15 @@ async fn main() -> std::io::Result<()> {
     });
 
     server.await
-}
\ No newline at end of file
+}
+
+async fn fetch_avatar() -> Option<DynamicImage> {
+    if let Ok(response) = reqwest::get("https://github.com/namishh.png").await
+        && response.status().is_success()
+        && let Ok(bytes) = response.bytes().await
+        && let Ok(img) = load_from_memory(&bytes)
+    {
+        return Some(img);
+    }
+    None
+}
@@ -1,12 +1,21 @@
-use pulldown_cmark::{Parser, Options, html, Tag, TagEnd, CodeBlockKind, Event};
+use pulldown_cmark::{Alignment, Parser, Options, html, Tag, TagEnd, CodeBlockKind, Event, LinkType, TextMergeStream};
 use inkjet::{Highlighter, Language, formatter};
+use latex2mathml::DisplayStyle;
 use std::collections::{HashMap, HashSet};
 use lazy_static::lazy_static;
+use once_cell::sync::Lazy;
 use regex::Regex;
-use htmlescape;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
+use std::time::SystemTime;
+use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 
+use crate::cache::Heading;
+use crate::content::{ContentIndex, CONTENT_INDEX};
+use crate::diagram::{is_diagram, render_diagram};
+
 lazy_static! {
    pub static ref LANGUAGE_MAP: HashMap<&'static str, Language> = {
         let mut m = HashMap::new();
@@ -25,13 +34,126 @@ lazy_static! {
         m.insert("zig", Language::Zig);
         m.insert("nix", Language::Nix);
         m.insert("glsl", Language::Glsl);
+        // Everything else inkjet has a grammar for is found by `Language::from_token`; these
+        // are the names it does not know.
+        m.insert("yml", Language::Yaml);
+        m.insert("zsh", Language::Bash);
+        m.insert("conf", Language::Ini);
+        m.insert("cfg", Language::Ini);
+        m.insert("jsonc", Language::Json);
+        // No grammar, but not a typo either.
+        m.insert("text", Language::Plaintext);
+        m.insert("txt", Language::Plaintext);
+        m.insert("md", Language::Plaintext);
+        m.insert("markdown", Language::Plaintext);
+        m.insert("odin", Language::Plaintext);
         m
     };
    pub static ref FRONTMATTER_REGEX: Regex = Regex::new(r"(?s)^-{3,}\s*\n(.*?)\n-{3,}\s*\n(.*)").unwrap();
+   // `> [!WARNING]- Optional title` on the first line of a blockquote.
+   static ref CALLOUT_REGEX: Regex = Regex::new(r"^\[!([A-Za-z]+)\]([+-]?)[ \t]*(.*)$").unwrap();
+   static ref MATHML_TAG_REGEX: Regex = Regex::new(r"</?m[a-z]*(?:\s[^<>]*)?>").unwrap();
+   // `file="snippets/main.rs"` and `lines=10-40` in a fence's info string.
+   static ref SNIPPET_FILE_REGEX: Regex = Regex::new(r#"(?:^|\s)file=(?:"([^"]*)"|'([^']*)'|(\S+))"#).unwrap();
+   static ref MAXLINES_REGEX: Regex = Regex::new(r"(?:^|\s)maxlines=(\d+)(?:\s|$)").unwrap();
+   static ref SNIPPET_LINES_REGEX: Regex = Regex::new(r"(?:^|\s)lines=(\d+)(?:-(\d*))?(?:\s|$)").unwrap();
+}
+
+/// Where code blocks with a `file=` attribute load their code from: `$SNIPPETS_DIR`, or
+/// `snippets` in the working directory.
+pub static SNIPPETS_DIR: Lazy<PathBuf> = Lazy::new(|| {
+    std::env::var_os("SNIPPETS_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("snippets"))
+});
+
+struct Callout {
+    kind: String,
+    title: String,
+    /// `None` for a plain callout, otherwise whether the foldable callout starts open.
+    open: Option<bool>,
+}
+
+fn parse_callout(text: &str) -> Option<Callout> {
+    let captures = CALLOUT_REGEX.captures(text)?;
+    Some(Callout {
+        kind: captures[1].to_lowercase(),
+        title: captures[3].to_string(),
+        open: match &captures[2] {
+            "+" => Some(true),
+            "-" => Some(false),
+            _ => None,
+        },
+    })
+}
+
+// Style class and Phosphor icon for each callout type; unknown types look like notes.
+fn callout_style(kind: &str) -> (&'static str, &'static str) {
+    match kind {
+        "tip" | "hint" => ("tip", "ph-lightbulb"),
+        "important" => ("important", "ph-star"),
+        "warning" | "caution" | "attention" => ("warning", "ph-warning"),
+        "danger" | "error" | "failure" | "bug" => ("danger", "ph-x-circle"),
+        "success" | "check" | "done" => ("success", "ph-check-circle"),
+        "question" | "help" | "faq" => ("question", "ph-question"),
+        "example" => ("example", "ph-list-bullets"),
+        "quote" | "cite" => ("quote", "ph-quotes"),
+        "abstract" | "summary" | "tldr" => ("abstract", "ph-clipboard-text"),
+        _ => ("note", "ph-info"),
+    }
 }
 
 fn get_inkjet_language(lang_str: &str) -> Option<Language> {
-    LANGUAGE_MAP.get(lang_str.to_lowercase().as_str()).cloned()
+    let lang = lang_str.to_lowercase();
+    LANGUAGE_MAP.get(lang.as_str()).cloned().or_else(|| Language::from_token(&lang))
+}
+
+/// A fenced code block whose language has no grammar, most likely a typo.
+#[derive(Debug, Clone, Serialize)]
+pub struct UnknownLanguage {
+    pub file: String,
+    pub line: usize,
+    pub language: String,
+}
+
+/// Every code block across all pages, drafts included, that is tagged with a language that
+/// is neither highlighted nor drawn as a diagram.
+pub fn find_unknown_languages() -> Vec<UnknownLanguage> {
+    let content = CONTENT_INDEX.read().unwrap();
+    let mut unknown = Vec::new();
+    for entry in content.entries() {
+        for (event, range) in Parser::new_ext(&entry.body, parser_options()).into_offset_iter() {
+            let Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) = event else {
+                continue;
+            };
+            if let (Some(lang), _) = extract_language_and_filename(&info)
+                && !is_known_language(&lang)
+            {
+                unknown.push(UnknownLanguage {
+                    file: entry.source.to_string_lossy().replace('\\', "/"),
+                    line: entry.line(range.start),
+                    language: lang,
+                });
+            }
+        }
+    }
+    unknown
+}
+
+fn is_known_language(lang: &str) -> bool {
+    let lang = lang.to_lowercase();
+    get_inkjet_language(&lang).is_some()
+        || is_diagram(&lang)
+        || is_console(&lang)
+        || diff_language(&lang).is_some_and(|language| language.is_some())
+}
+
+/// Warns about every code block in a language that will not be highlighted; called once at
+/// startup.
+pub fn report_unknown_languages() {
+    for block in find_unknown_languages() {
+        eprintln!("Unknown code block language in {}:{}: {}", block.file, block.line, block.language);
+    }
 }
 
 fn extract_language_and_filename(info_string: &str) -> (Option<String>, Option<String>) {
@@ -77,11 +199,11 @@ fn parse_highlighting_info(info_string: &str) -> (HashSet<usize>, HashSet<usize>
             let part = part.trim();
             if part.contains('-') {
                 let range: Vec<&str> = part.split('-').collect();
-                if range.len() == 2 {
-                    if let (Ok(start), Ok(end)) = (range[0].trim().parse::<usize>(), range[1].trim().parse::<usize>()) {
-                        for i in start..=end {
-                            result.insert(i);
-                        }
+                if range.len() == 2
+                    && let (Ok(start), Ok(end)) = (range[0].trim().parse::<usize>(), range[1].trim().parse::<usize>())
+                {
+                    for i in start..=end {
+                        result.insert(i);
                     }
                 }
             } else if let Ok(num) = part.parse::<usize>() {
@@ -91,15 +213,15 @@ fn parse_highlighting_info(info_string: &str) -> (HashSet<usize>, HashSet<usize>
         result
     };
 
-    if let Some(captures) = DEL_RE.captures(info_string) {
-        if let Some(ranges) = captures.get(1) {
-            del_lines = parse_ranges(ranges.as_str());
-        }
+    if let Some(captures) = DEL_RE.captures(info_string)
+        && let Some(ranges) = captures.get(1)
+    {
+        del_lines = parse_ranges(ranges.as_str());
     }
-    if let Some(captures) = ADD_RE.captures(info_string) {
-        if let Some(ranges) = captures.get(1) {
-            add_lines = parse_ranges(ranges.as_str());
-        }
+    if let Some(captures) = ADD_RE.captures(info_string)
+        && let Some(ranges) = captures.get(1)
+    {
+        add_lines = parse_ranges(ranges.as_str());
     }
     for captures in H_RE.captures_iter(info_string) {
         if let Some(range_match) = captures.get(1) {
@@ -112,23 +234,648 @@ fn parse_highlighting_info(info_string: &str) -> (HashSet<usize>, HashSet<usize>
     (del_lines, add_lines, h_lines)
 }
 
-pub fn markdown_to_html(content: &str, highlighter: &Mutex<Highlighter>) -> (String, Vec<(u8, String, String)>) {
+// How many lines a code block shows before it is folded: `maxlines=30`, or a default for a
+// bare `collapse`.
+fn parse_fold_info(info_string: &str) -> Option<usize> {
+    if let Some(captures) = MAXLINES_REGEX.captures(info_string) {
+        return captures[1].parse().ok();
+    }
+    info_string
+        .split_whitespace()
+        .skip(1)
+        .any(|part| part == "collapse")
+        .then_some(COLLAPSED_LINES)
+}
+
+// A code block whose code lives in a file under `snippets/`.
+struct Snippet {
+    file: String,
+    /// First and last line to include, counting from 1; `None` for the end of the file.
+    lines: Option<(usize, Option<usize>)>,
+}
+
+fn parse_snippet_info(info_string: &str) -> Option<Snippet> {
+    let captures = SNIPPET_FILE_REGEX.captures(info_string)?;
+    let file = captures.get(1).or(captures.get(2)).or(captures.get(3))?.as_str().to_string();
+    let lines = SNIPPET_LINES_REGEX.captures(info_string).and_then(|captures| {
+        let start = captures[1].parse().ok()?;
+        let end = match captures.get(2).map(|m| m.as_str()) {
+            None => Some(start),
+            Some("") => None,
+            Some(end) => Some(end.parse().ok()?),
+        };
+        Some((start, end))
+    });
+    Some(Snippet { file, lines })
+}
+
+// Where `file=` points, as long as it stays inside the snippets directory. A leading
+// `snippets/` is optional.
+fn snippet_path(file: &str) -> Option<PathBuf> {
+    let relative = Path::new(file.strip_prefix("snippets/").unwrap_or(file));
+    relative
+        .components()
+        .all(|component| matches!(component, std::path::Component::Normal(_)))
+        .then(|| SNIPPETS_DIR.join(relative))
+}
+
+// The lines of the file at `path` that `snippet` asks for, and the file's mtime. An end
+// past the last line, as in `lines=10-` or a file that has since shrunk, stops at the end.
+fn load_snippet(path: &Path, snippet: &Snippet) -> Result<(SystemTime, String), String> {
+    let Some((modified, content)) = read_with_mtime(path) else {
+        return Err(format!("could not read {}", path.display()));
+    };
+
+    let all_lines: Vec<&str> = content.lines().collect();
+    let (start, end) = match snippet.lines {
+        Some((start, end)) => (start, end.map_or(all_lines.len(), |end| end.min(all_lines.len()))),
+        None => (1, all_lines.len()),
+    };
+    if start == 0 || start > all_lines.len() {
+        return Err(format!("{} has {} lines, there is no line {}", path.display(), all_lines.len(), start));
+    }
+    if start > end {
+        return Err(format!("lines={}-{} ends before it starts", start, end));
+    }
+
+    let mut code = all_lines[start - 1..end].join("\n");
+    code.push('\n');
+    Ok((modified, code))
+}
+
+/// Anchor id used for a heading with the given text.
+pub fn slugify(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .replace(' ', "-")
+        .replace(|c: char| !c.is_alphanumeric() && c != '-', "")
+}
+
+fn parser_options() -> Options {
     let mut options = Options::empty();
     options.insert(Options::ENABLE_STRIKETHROUGH);
     options.insert(Options::ENABLE_MATH);
-    let parser = Parser::new_ext(content, options);
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_TASKLISTS);
+    options.insert(Options::ENABLE_FOOTNOTES);
+    options.insert(Options::ENABLE_HEADING_ATTRIBUTES);
+    options.insert(Options::ENABLE_SMART_PUNCTUATION);
+    options.insert(Options::ENABLE_WIKILINKS);
+    options
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpanKind {
+    Text,
+    Heading,
+    Code,
+}
+
+/// A link in a page's markdown, as written.
+#[derive(Debug, Clone)]
+pub struct MarkdownLink {
+    pub dest: String,
+    /// `[[target]]` rather than `[text](dest)`.
+    pub wiki: bool,
+    /// `![alt](src)` or `![[embed]]`.
+    pub image: bool,
+    /// Byte offset of the link in `content`.
+    pub offset: usize,
+}
+
+/// Every link and image in `content`, `![[embeds]]` included.
+pub fn collect_links(content: &str) -> Vec<MarkdownLink> {
+    Parser::new_ext(content, parser_options())
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Start(Tag::Link { link_type, dest_url, .. }) => Some(MarkdownLink {
+                dest: dest_url.to_string(),
+                wiki: matches!(link_type, LinkType::WikiLink { .. }),
+                image: false,
+                offset: range.start,
+            }),
+            Event::Start(Tag::Image { link_type, dest_url, .. }) => Some(MarkdownLink {
+                dest: dest_url.to_string(),
+                wiki: matches!(link_type, LinkType::WikiLink { .. }),
+                image: true,
+                offset: range.start,
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The part of `content` from the heading matching `heading` (by slug or `{#id}`) up to the
+/// next heading of the same or a higher level.
+pub fn extract_section<'a>(content: &'a str, heading: &str) -> Option<&'a str> {
+    let target = slugify(heading);
+    let mut section: Option<(usize, pulldown_cmark::HeadingLevel)> = None;
+    let mut current: Option<(usize, pulldown_cmark::HeadingLevel, Option<String>, String)> = None;
+
+    for (event, range) in Parser::new_ext(content, parser_options()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Heading { level, id, .. }) => {
+                if let Some((start, section_level)) = section
+                    && level <= section_level
+                {
+                    return Some(&content[start..range.start]);
+                }
+                current = Some((range.start, level, id.map(|id| id.to_string()), String::new()));
+            }
+            Event::Text(text) => {
+                if let Some((_, _, _, heading_text)) = current.as_mut() {
+                    heading_text.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some((start, level, id, heading_text)) = current.take()
+                    && section.is_none()
+                    && (id.as_deref() == Some(target.as_str()) || slugify(&heading_text) == target)
+                {
+                    section = Some((start, level));
+                }
+            }
+            _ => {}
+        }
+    }
+    section.map(|(start, _)| &content[start..])
+}
+
+// Opening tag for `[[target#heading]]`, plus the tag that closes it. Targets that match no
+// page are rendered as a marked, non-clickable span.
+fn wikilink_html(target: &str, index: &ContentIndex) -> (String, &'static str) {
+    let (page, heading) = match target.split_once('#') {
+        Some((page, heading)) => (page, Some(heading)),
+        None => (target, None),
+    };
+    let fragment = heading.map(|heading| format!("#{}", slugify(heading))).unwrap_or_default();
+
+    if page.trim().is_empty() {
+        return (format!(r#"<a href="{}" class="wikilink">"#, htmlescape::encode_minimal(&fragment)), "</a>");
+    }
 
+    match index.resolve_wikilink(page).map(|entry| &entry.page) {
+        Some(page) => (
+            format!(
+                r#"<a href="/{}{}" class="wikilink">"#,
+                htmlescape::encode_minimal(&page.url),
+                htmlescape::encode_minimal(&fragment)
+            ),
+            "</a>",
+        ),
+        None => (
+            format!(
+                r#"<span class="wikilink wikilink-broken" title="No page called {}">"#,
+                htmlescape::encode_minimal(page)
+            ),
+            "</span>",
+        ),
+    }
+}
+
+/// A run of plain text as a reader sees it on the rendered page, along with the slug of
+/// the heading it sits under.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TextSpan {
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heading: Option<String>,
+    pub kind: SpanKind,
+}
+
+/// Walks the same event stream as `markdown_to_html` and keeps only the visible text,
+/// one span per block. Markup, link targets, images, raw HTML and math are dropped.
+pub fn extract_text_spans(content: &str) -> Vec<TextSpan> {
+    let parser = TextMergeStream::new(Parser::new_ext(content, parser_options()));
+
+    let mut spans = Vec::new();
+    let mut current = String::new();
+    let mut current_slug: Option<String> = None;
+    // Slugs are built from the heading's text events only, exactly like `markdown_to_html`.
+    let mut heading_slug_text: Option<String> = None;
+    let mut heading_id: Option<String> = None;
+    let mut image_depth = 0;
+
+    let flush = |spans: &mut Vec<TextSpan>, text: &mut String, heading: &Option<String>, kind: SpanKind| {
+        let trimmed = text.trim();
+        if !trimmed.is_empty() {
+            spans.push(TextSpan {
+                text: trimmed.to_string(),
+                heading: heading.clone(),
+                kind,
+            });
+        }
+        text.clear();
+    };
+
+    for event in parser {
+        match event {
+            Event::Start(Tag::Image { .. }) => image_depth += 1,
+            Event::End(TagEnd::Image) => image_depth -= 1,
+            _ if image_depth > 0 => {}
+            Event::Start(Tag::Heading { level, id, .. }) => {
+                flush(&mut spans, &mut current, &current_slug, SpanKind::Text);
+                if level <= pulldown_cmark::HeadingLevel::H4 {
+                    heading_slug_text = Some(String::new());
+                    heading_id = id.map(|id| id.to_string());
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some(slug_text) = heading_slug_text.take() {
+                    current_slug = Some(heading_id.take().unwrap_or_else(|| slugify(&slug_text)));
+                }
+                flush(&mut spans, &mut current, &current_slug, SpanKind::Heading);
+            }
+            Event::Start(Tag::CodeBlock(_)) => {
+                flush(&mut spans, &mut current, &current_slug, SpanKind::Text);
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                flush(&mut spans, &mut current, &current_slug, SpanKind::Code);
+            }
+            Event::Start(Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Superscript | Tag::Subscript | Tag::Link { .. })
+            | Event::End(TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough | TagEnd::Superscript | TagEnd::Subscript | TagEnd::Link) => {}
+            Event::Start(_) | Event::End(_) => {
+                flush(&mut spans, &mut current, &current_slug, SpanKind::Text);
+            }
+            Event::Text(text) => {
+                if let Some(slug_text) = heading_slug_text.as_mut() {
+                    slug_text.push_str(&text);
+                }
+                // Only the title of a callout is visible, not its `[!TYPE]` marker.
+                match parse_callout(&text).filter(|_| current.is_empty()) {
+                    Some(callout) => current.push_str(&callout.title),
+                    None => current.push_str(&text),
+                }
+            }
+            Event::Code(text) => current.push_str(&text),
+            Event::SoftBreak | Event::HardBreak => current.push(' '),
+            _ => {}
+        }
+    }
+    flush(&mut spans, &mut current, &current_slug, SpanKind::Text);
+
+    spans
+}
+
+/// How footnotes are laid out (`notes:` in frontmatter).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteStyle {
+    /// A numbered list at the end of the page.
+    #[default]
+    Bottom,
+    /// In the margin next to the reference, collapsed behind the number on small screens.
+    Side,
+}
+
+/// Per-page switches for `markdown_to_html`.
+#[derive(Debug, Clone, Default)]
+pub struct MarkdownOptions {
+    pub notes: NoteStyle,
+    /// URL of the page being rendered, so that it cannot embed itself.
+    pub url: Option<String>,
+}
+
+/// A rendered page along with what it was rendered from.
+pub struct RenderedMarkdown {
+    pub html: String,
+    pub headings: Vec<Heading>,
+    /// Files embedded with `![[note]]` or included in code blocks, at any depth, and their
+    /// mtimes when they were read.
+    pub dependencies: Vec<(PathBuf, SystemTime)>,
+    /// Some math could not be turned into MathML and was left for KaTeX to render.
+    pub math_fallback: bool,
+}
+
+// Embeds nested deeper than this are not rendered, whether they loop or not.
+const MAX_EMBED_DEPTH: usize = 8;
+
+// Lines a block marked `collapse` shows until it is expanded.
+const COLLAPSED_LINES: usize = 10;
+
+// Shared by a page and the notes embedded in it while it renders.
+struct RenderState<'a> {
+    /// Where wikilinks and embeds are looked up.
+    content: &'a ContentIndex,
+    /// Pages currently being rendered, outermost first.
+    embed_stack: Vec<String>,
+    dependencies: Vec<(PathBuf, SystemTime)>,
+    math_fallback: bool,
+    /// Code blocks rendered so far, numbering their line anchors (`#code-3-L12`).
+    code_blocks: usize,
+    /// Footnotes numbered so far, so an embedded note's continue from the page's.
+    footnotes: usize,
+    /// Put in front of footnote and sidenote ids of the note being rendered, empty for the
+    /// page itself, so that an embedded note's `fn-1` does not clash with the page's.
+    note_prefix: String,
+    /// How often each note has been embedded so far, to tell the copies apart.
+    embeds: HashMap<String, usize>,
+}
+
+pub fn markdown_to_html(content: &str, highlighter: &Mutex<Highlighter>, options: &MarkdownOptions) -> RenderedMarkdown {
+    markdown_to_html_with(&CONTENT_INDEX.read().unwrap(), content, highlighter, options)
+}
+
+/// `markdown_to_html`, resolving wikilinks and embeds against `index` rather than the
+/// global content index.
+pub fn markdown_to_html_with(
+    index: &ContentIndex,
+    content: &str,
+    highlighter: &Mutex<Highlighter>,
+    options: &MarkdownOptions,
+) -> RenderedMarkdown {
+    let mut state = RenderState {
+        content: index,
+        embed_stack: options.url.iter().cloned().collect(),
+        dependencies: Vec::new(),
+        math_fallback: false,
+        code_blocks: 0,
+        footnotes: 0,
+        note_prefix: String::new(),
+        embeds: HashMap::new(),
+    };
+    let (html, headings) = render_markdown(content, highlighter, options, &mut state);
+    RenderedMarkdown {
+        html,
+        headings,
+        dependencies: state.dependencies,
+        math_fallback: state.math_fallback,
+    }
+}
+
+// `![[note]]` or `![[note#heading]]`: the other page's rendered content, or a notice saying
+// why it cannot be shown. `None` when the target is not a page, so it stays an image.
+fn embed_note(target: &str, highlighter: &Mutex<Highlighter>, options: &MarkdownOptions, state: &mut RenderState<'_>) -> Option<String> {
+    let (page, heading) = match target.split_once('#') {
+        Some((page, heading)) => (page, Some(heading)),
+        None => (target, None),
+    };
+    let entry = state.content.resolve_wikilink(page)?;
+    let (url, title, source) = (entry.page.url.clone(), entry.page.title.clone(), entry.source.clone());
+    let notice = |message: String| {
+        Some(format!(
+            r#"<div class="transclusion transclusion-error">{}</div>"#,
+            htmlescape::encode_minimal(&message)
+        ))
+    };
+
+    if state.embed_stack.contains(&url) || state.embed_stack.len() >= MAX_EMBED_DEPTH {
+        return notice(format!("Cannot embed “{}” inside itself", title));
+    }
+
+    // Read from disk rather than the content index, so the recorded mtime matches the text.
+    let Some((modified, raw_content)) = read_with_mtime(&source) else {
+        return notice(format!("Could not read “{}”", title));
+    };
+    state.dependencies.push((source, modified));
+
+    let (_, body) = extract_frontmatter(&raw_content);
+    let (body, fragment) = match heading {
+        Some(heading) => match extract_section(body, heading) {
+            Some(section) => (section, format!("#{}", slugify(heading))),
+            None => return notice(format!("No section “{}” in “{}”", heading, title)),
+        },
+        None => (body, String::new()),
+    };
+
+    let copies = state.embeds.entry(url.clone()).or_default();
+    *copies += 1;
+    let prefix = match *copies {
+        1 => format!("{}{}-", state.note_prefix, slugify(&url)),
+        n => format!("{}{}-{}-", state.note_prefix, slugify(&url), n),
+    };
+    let outer_prefix = std::mem::replace(&mut state.note_prefix, prefix);
+    state.embed_stack.push(url.clone());
+    let (html, _) = render_markdown(body, highlighter, options, state);
+    state.embed_stack.pop();
+    state.note_prefix = outer_prefix;
+
+    Some(format!(
+        r#"<div class="transclusion"><div class="transclusion-source"><a href="/{}{}"><i class="ph ph-arrow-square-out"></i> {}</a></div>{}</div>"#,
+        htmlescape::encode_minimal(&url),
+        htmlescape::encode_minimal(&fragment),
+        htmlescape::encode_minimal(&title),
+        html
+    ))
+}
+
+// `latex` as MathML, or `None` when latex2mathml cannot handle it. It reports problems by
+// returning an error, by panicking, or by leaving a `[PARSE ERROR: ...]` in the output.
+fn latex_to_mathml(latex: &str, display: DisplayStyle) -> Option<String> {
+    let mathml = std::panic::catch_unwind(|| latex2mathml::latex_to_mathml(latex, display))
+        .ok()?
+        .ok()?;
+    if mathml.contains("[PARSE ERROR") {
+        return None;
+    }
+
+    // Operators like `<` come out unescaped, as in `<mo><</mo>`.
+    let mut escaped = String::with_capacity(mathml.len());
+    let mut last = 0;
+    for tag in MATHML_TAG_REGEX.find_iter(&mathml) {
+        escaped.push_str(&mathml[last..tag.start()].replace('<', "&lt;").replace('>', "&gt;"));
+        escaped.push_str(tag.as_str());
+        last = tag.end();
+    }
+    escaped.push_str(&mathml[last..].replace('<', "&lt;").replace('>', "&gt;"));
+    Some(escaped)
+}
+
+// One line of a rendered code block: what is shown before it, its html, and whether it is
+// code for the copy button or output shown alongside.
+struct CodeLine {
+    prefix: String,
+    html: String,
+    code: bool,
+}
+
+impl CodeLine {
+    fn code(html: String) -> Self {
+        CodeLine { prefix: String::new(), html, code: true }
+    }
+}
+
+// `code` highlighted in `language`, or escaped if there is none, one string per line.
+fn highlight_lines(highlighter: &Mutex<Highlighter>, language: Option<Language>, code: &str) -> Vec<String> {
+    let html = match language {
+        Some(language) => match highlighter.lock().unwrap().highlight_to_string(language, &formatter::Html, code) {
+            Ok(html) => html,
+            Err(e) => {
+                eprintln!("Error highlighting code: {}", e);
+                htmlescape::encode_minimal(code)
+            }
+        },
+        None => htmlescape::encode_minimal(code),
+    };
+    html.lines().map(str::to_string).collect()
+}
+
+// The highlighter for the code under a `diff` or `diff-rust` fence's `+`/`-` column, or
+// `None` if `lang` is not a diff.
+fn diff_language(lang: &str) -> Option<Option<Language>> {
+    if lang == "diff" {
+        return Some(None);
+    }
+    lang.strip_prefix("diff-").map(get_inkjet_language)
+}
+
+fn is_console(lang: &str) -> bool {
+    matches!(lang, "console" | "shell-session")
+}
+
+// The lines of a code block in `lang`, along with the lines a diff adds and deletes.
+fn layout_code_lines(
+    highlighter: &Mutex<Highlighter>,
+    lang: Option<&str>,
+    code: &str,
+) -> (Vec<CodeLine>, HashSet<usize>, HashSet<usize>) {
+    let lang = lang.map(str::to_lowercase);
+    if let Some(lang) = lang.as_deref() {
+        if is_console(lang) {
+            return (console_lines(highlighter, code), HashSet::new(), HashSet::new());
+        }
+        if let Some(language) = diff_language(lang) {
+            return diff_lines(highlighter, language, code);
+        }
+    }
+    let language = lang.as_deref().and_then(get_inkjet_language);
+    let lines = highlight_lines(highlighter, language, code).into_iter().map(CodeLine::code).collect();
+    (lines, HashSet::new(), HashSet::new())
+}
+
+// `+`, `-` and ` ` become a marker beside the code, which is highlighted on its own and
+// coloured by what the line does. File and hunk headers are shown but not copied.
+fn diff_lines(
+    highlighter: &Mutex<Highlighter>,
+    language: Option<Language>,
+    code: &str,
+) -> (Vec<CodeLine>, HashSet<usize>, HashSet<usize>) {
+    // File headers only come before the first hunk; after it `--- x` is a deleted `-- x`.
+    let first_hunk = code.lines().position(|line| line.starts_with("@@"));
+    let headers: Vec<bool> = code
+        .lines()
+        .enumerate()
+        .map(|(i, line)| match first_hunk {
+            Some(hunk) if i < hunk => ["diff ", "index ", "--- ", "+++ "].iter().any(|prefix| line.starts_with(prefix)),
+            _ => line.starts_with(['@', '\\']),
+        })
+        .collect();
+    let source: Vec<&str> = code
+        .lines()
+        .zip(&headers)
+        .filter(|(_, header)| !**header)
+        .map(|(line, _)| line.strip_prefix(['+', '-', ' ']).unwrap_or(line))
+        .collect();
+    let mut highlighted = highlight_lines(highlighter, language, &source.join("\n")).into_iter();
+
+    let mut lines = Vec::new();
+    let mut added = HashSet::new();
+    let mut deleted = HashSet::new();
+    for (i, (line, header)) in code.lines().zip(headers).enumerate() {
+        if header {
+            lines.push(CodeLine {
+                prefix: String::new(),
+                html: htmlescape::encode_minimal(line),
+                code: false,
+            });
+            continue;
+        }
+        let marker = match line.chars().next() {
+            Some('+') => {
+                added.insert(i + 1);
+                "+"
+            }
+            Some('-') => {
+                deleted.insert(i + 1);
+                "-"
+            }
+            _ => " ",
+        };
+        lines.push(CodeLine {
+            prefix: format!(r#"<span class="diff-marker">{}</span>"#, marker),
+            html: highlighted.next().unwrap_or_default(),
+            code: true,
+        });
+    }
+    (lines, added, deleted)
+}
+
+// `$ ` lines, and the lines continuing them after a trailing `\`, are commands highlighted as
+// shell with the prompt kept out of the copy; everything else is their output.
+fn console_lines(highlighter: &Mutex<Highlighter>, code: &str) -> Vec<CodeLine> {
+    let mut commands = Vec::new();
+    let mut is_command = Vec::new();
+    let mut continued = false;
+    for line in code.lines() {
+        let command = match line.strip_prefix("$ ").or_else(|| (line == "$").then_some("")) {
+            Some(command) => Some(command),
+            None if continued => Some(line),
+            None => None,
+        };
+        continued = command.is_some_and(|command| command.ends_with('\\'));
+        if let Some(command) = command {
+            commands.push(command);
+        }
+        is_command.push(command.is_some());
+    }
+    let mut highlighted = highlight_lines(highlighter, Some(Language::Bash), &commands.join("\n")).into_iter();
+
+    code.lines()
+        .zip(is_command)
+        .map(|(line, is_command)| {
+            if !is_command {
+                return CodeLine { prefix: String::new(), html: htmlescape::encode_minimal(line), code: false };
+            }
+            let prompt = if line.starts_with('$') {
+                r#"<span class="shell-prompt">$ </span>"#.to_string()
+            } else {
+                String::new()
+            };
+            CodeLine { prefix: prompt, html: highlighted.next().unwrap_or_default(), code: true }
+        })
+        .collect()
+}
+
+fn read_with_mtime(path: &Path) -> Option<(SystemTime, String)> {
+    let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok()?;
+    let content = fs::read_to_string(path).ok()?;
+    Some((modified, content))
+}
+
+fn render_markdown(
+    content: &str,
+    highlighter: &Mutex<Highlighter>,
+    options: &MarkdownOptions,
+    state: &mut RenderState<'_>,
+) -> (String, Vec<Heading>) {
+    let mut parser = TextMergeStream::new(Parser::new_ext(content, parser_options())).peekable();
+
+    // Embedded notes number their footnotes in place, so each one reserves a run of numbers
+    // after the page's notes referenced before it: (page notes before it, notes it took).
+    let first_note = state.footnotes + 1;
+    let mut reserved_notes: Vec<(usize, usize)> = Vec::new();
     let mut in_code_block = false;
     let mut code_content = String::new();
     let mut current_language = None;
     let mut current_filename = None;
+    let mut current_snippet = None;
+    let mut current_fold = None;
     let mut current_heading: Option<(u8, Vec<Event>)> = None;
+    let mut heading_id: Option<String> = None;
+    let mut heading_attributes = String::new();
     let mut headings = Vec::new();
+    let mut table_alignments: Vec<Alignment> = Vec::new();
+    let mut table_column = 0;
+    let mut in_table_head = false;
+    let mut wikilink_close: Option<&'static str> = None;
+    // Closing markup for each open blockquote; `None` for plain ones.
+    let mut blockquotes: Vec<Option<&'static str>> = Vec::new();
     let mut current_highlighting: (HashSet<usize>, HashSet<usize>, HashSet<usize>) = 
         (HashSet::new(), HashSet::new(), HashSet::new());
     let mut events = Vec::new();
 
-    for event in parser {
+    while let Some(event) = parser.next() {
         match event {
             Event::Start(Tag::CodeBlock(kind)) => {
                 in_code_block = true;
@@ -140,46 +887,84 @@ pub fn markdown_to_html(content: &str, highlighter: &Mutex<Highlighter>) -> (Str
                 current_language = lang;
                 current_filename = filename;
                 current_highlighting = parse_highlighting_info(&lang_info);
+                current_snippet = parse_snippet_info(&lang_info);
+                current_fold = parse_fold_info(&lang_info);
                 code_content.clear();
             }
             Event::Text(text) if in_code_block => {
                 code_content.push_str(&text);
             }
             Event::DisplayMath(text) => {
-                let math_html = format!(
-                    r#"<div class="math math-display">\[{}\]</div>"#,
-                    htmlescape::encode_minimal(&text)
-                );
+                let math_html = match latex_to_mathml(&text, DisplayStyle::Block) {
+                    Some(mathml) => format!(r#"<div class="math math-display">{}</div>"#, mathml),
+                    None => {
+                        state.math_fallback = true;
+                        format!(r#"<div class="math math-display">\[{}\]</div>"#, htmlescape::encode_minimal(&text))
+                    }
+                };
                 events.push(Event::Html(math_html.into()));
             },
             Event::InlineMath(text) => {
-                let math_html = format!(
-                    r#"<span class="math math-inline">\({}\)</span>"#,
-                    htmlescape::encode_minimal(&text)
-                );
+                let math_html = match latex_to_mathml(&text, DisplayStyle::Inline) {
+                    Some(mathml) => format!(r#"<span class="math math-inline">{}</span>"#, mathml),
+                    None => {
+                        state.math_fallback = true;
+                        format!(r#"<span class="math math-inline">\({}\)</span>"#, htmlescape::encode_minimal(&text))
+                    }
+                };
                 events.push(Event::Html(math_html.into()));
             },
             Event::End(TagEnd::CodeBlock) if in_code_block => {
                 in_code_block = false;
-                let highlighted_html = if let Some(lang_str) = current_language.as_ref() {
-                    if let Some(inkjet_lang) = get_inkjet_language(lang_str) {
-                        match highlighter.lock().unwrap().highlight_to_string(inkjet_lang, &formatter::Html, &code_content) {
-                            Ok(html) => html,
-                            Err(e) => {
-                                eprintln!("Error highlighting code: {}", e);
-                                htmlescape::encode_minimal(&code_content)
-                            }
+                if let Some(snippet) = current_snippet.take() {
+                    let loaded = snippet_path(&snippet.file)
+                        .ok_or_else(|| format!("the path must stay inside {}", SNIPPETS_DIR.display()))
+                        .and_then(|path| {
+                            // Recorded even when the file is missing, so the page is not
+                            // cached without it.
+                            let result = load_snippet(&path, &snippet);
+                            let modified = result.as_ref().map(|(modified, _)| *modified).unwrap_or(SystemTime::UNIX_EPOCH);
+                            state.dependencies.push((path, modified));
+                            result
+                        });
+                    match loaded {
+                        Ok((_, code)) => code_content = code,
+                        Err(e) => {
+                            eprintln!("Could not include {} in a code block: {}", snippet.file, e);
+                            events.push(Event::Html(format!(
+                                r#"<div class="snippet-error">Could not include {}: {}</div>"#,
+                                htmlescape::encode_minimal(&snippet.file),
+                                htmlescape::encode_minimal(&e)
+                            ).into()));
+                            current_language = None;
+                            current_filename = None;
+                            current_fold = None;
+                            current_highlighting = (HashSet::new(), HashSet::new(), HashSet::new());
+                            continue;
                         }
-                    } else {
-                        htmlescape::encode_minimal(&code_content)
                     }
-                } else {
-                    htmlescape::encode_minimal(&code_content)
-                };
-                let lines: Vec<&str> = highlighted_html.lines().collect();
+                }
+                if let Some(lang) = current_language.as_deref().filter(|lang| is_diagram(lang))
+                    && let Some(svg) = render_diagram(lang, &code_content)
+                {
+                    events.push(Event::Html(format!(
+                        r#"<figure class="diagram">{}<details class="diagram-source"><summary>source</summary><pre><code>{}</code></pre></details></figure>"#,
+                        svg,
+                        htmlescape::encode_minimal(&code_content)
+                    ).into()));
+                    current_language = None;
+                    current_filename = None;
+                    current_highlighting = (HashSet::new(), HashSet::new(), HashSet::new());
+                    continue;
+                }
+                let (lines, added, deleted) = layout_code_lines(highlighter, current_language.as_deref(), &code_content);
                 let total_lines = lines.len();
                 let width_needed = if total_lines > 0 { total_lines.to_string().len() } else { 1 };
-                let (del_lines, add_lines, highlight_lines) = &current_highlighting;
+                let (del_lines, add_lines, highlight_lines) = &mut current_highlighting;
+                del_lines.extend(deleted);
+                add_lines.extend(added);
+                state.code_blocks += 1;
+                let block_id = format!("code-{}", state.code_blocks);
                 let line_numbered_html = lines
                     .iter()
                     .enumerate()
@@ -194,25 +979,44 @@ pub fn markdown_to_html(content: &str, highlighter: &Mutex<Highlighter>) -> (Str
                             line_class = " class=\"highlight\"".to_string();
                         }
                         format!(
-                            "<span{line_class}><span class=\"line-number\">{:0width$}</span><span class=\"code-line\">{}</span></span>", 
+                            "<span id=\"{block_id}-L{line_num}\"{line_class}><a class=\"line-number\" href=\"#{block_id}-L{line_num}\">{:0width$}</a>{}<span class=\"{}\">{}</span></span>", 
                             line_num, 
-                            line,
+                            line.prefix,
+                            if line.code { "code-line" } else { "code-output" },
+                            line.html,
                             width = width_needed,
-                            line_class = line_class
+                            line_class = line_class,
+                            block_id = block_id,
+                            line_num = line_num
                         )
                     })
                     .collect::<Vec<String>>()
                     .join("\n");
+                // Long blocks are cut off at `maxlines` until expanded.
+                let (fold_attributes, expand_button) = match current_fold.take() {
+                    Some(max_lines) if total_lines > max_lines => (
+                        format!(r#" code-collapsed" style="--code-lines: {}"#, max_lines),
+                        format!(
+                            r#"<button class="code-expand" onclick="expandCode(this)"><i class="ph ph-caret-down"></i> expand, {} more lines</button>"#,
+                            total_lines - max_lines
+                        ),
+                    ),
+                    _ => (String::new(), String::new()),
+                };
                 let code_html = if let Some(filename) = current_filename.as_ref() {
                     format!(
-                        r#"<div class="code-block"><div class="code-header flex items-center justify-end"><span class="code-filename">{}</span><button class="copy-button" onclick="copyCode(this)"><i class="ph ph-copy"></i></button></div><pre><code>{}</code></pre></div>"#,
+                        r#"<div class="code-block{}"><div class="code-header flex items-center justify-end"><span class="code-filename">{}</span><button class="copy-button" onclick="copyCode(this)"><i class="ph ph-copy"></i></button></div><pre><code>{}</code></pre>{}</div>"#,
+                        fold_attributes,
                         filename,
-                        line_numbered_html
+                        line_numbered_html,
+                        expand_button
                     )
                 } else {
                     format!(
-                        r#"<div class="code-block"><div class="code-header flex items-center justify-end"><button class="copy-button" onclick="copyCode(this)"><i class="ph ph-copy"></i></button></div><pre><code>{}</code></pre></div>"#,
-                        line_numbered_html
+                        r#"<div class="code-block{}"><div class="code-header flex items-center justify-end"><button class="copy-button" onclick="copyCode(this)"><i class="ph ph-copy"></i></button></div><pre><code>{}</code></pre>{}</div>"#,
+                        fold_attributes,
+                        line_numbered_html,
+                        expand_button
                     )
                 };
                 events.push(Event::Html(code_html.into()));
@@ -220,7 +1024,22 @@ pub fn markdown_to_html(content: &str, highlighter: &Mutex<Highlighter>) -> (Str
                 current_filename = None;
                 current_highlighting = (HashSet::new(), HashSet::new(), HashSet::new());
             }
-            Event::Start(Tag::Heading { level, .. }) => {
+            Event::Start(Tag::Heading { level, id, classes, attrs }) if level <= pulldown_cmark::HeadingLevel::H4 => {
+                // `## Title {#custom-id .class key=value}`
+                heading_id = id.map(|id| id.to_string());
+                heading_attributes.clear();
+                if !classes.is_empty() {
+                    heading_attributes.push_str(&format!(" class=\"{}\"", htmlescape::encode_minimal(&classes.join(" "))));
+                }
+                for (key, value) in attrs {
+                    if key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                        heading_attributes.push_str(&format!(
+                            " {}=\"{}\"",
+                            key,
+                            htmlescape::encode_minimal(value.as_deref().unwrap_or(""))
+                        ));
+                    }
+                }
                 current_heading = match level {
                     pulldown_cmark::HeadingLevel::H1 => Some((1, Vec::new())),
                     pulldown_cmark::HeadingLevel::H2 => Some((2, Vec::new())),
@@ -229,7 +1048,7 @@ pub fn markdown_to_html(content: &str, highlighter: &Mutex<Highlighter>) -> (Str
                     _ => None,
                 };
             }
-            Event::End(TagEnd::Heading(_)) => {
+            Event::End(TagEnd::Heading(_)) if current_heading.is_some() => {
                 if let Some((level, inner_events)) = current_heading.take() {
                     let mut text_content = String::new();
                     for e in &inner_events {
@@ -237,18 +1056,197 @@ pub fn markdown_to_html(content: &str, highlighter: &Mutex<Highlighter>) -> (Str
                             text_content.push_str(t);
                         }
                     }
-                    let slug = text_content
-                        .trim()
-                        .to_lowercase()
-                        .replace(' ', "-")
-                        .replace(|c: char| !c.is_alphanumeric() && c != '-', "");
+                    let slug = heading_id.take().unwrap_or_else(|| slugify(&text_content));
                     headings.push((level, text_content.clone(), slug.clone()));
                     let mut inner_html = String::new();
                     html::push_html(&mut inner_html, inner_events.into_iter());
-                    let heading_html = format!("<h{} id=\"{}\">{}</h{}>", level, slug, inner_html, level);
+                    let heading_html = format!(
+                        "<h{} id=\"{}\"{}>{}</h{}>",
+                        level,
+                        htmlescape::encode_minimal(&slug),
+                        heading_attributes,
+                        inner_html,
+                        level
+                    );
                     events.push(Event::Html(heading_html.into()));
                 }
             }
+            Event::Start(Tag::Table(alignments)) => {
+                table_alignments = alignments;
+                events.push(Event::Html(r#"<div class="table-wrapper"><table class="md-table">"#.into()));
+            }
+            Event::End(TagEnd::Table) => {
+                events.push(Event::Html("</tbody></table></div>".into()));
+            }
+            Event::Start(Tag::TableHead) => {
+                in_table_head = true;
+                table_column = 0;
+                events.push(Event::Html("<thead><tr>".into()));
+            }
+            Event::End(TagEnd::TableHead) => {
+                in_table_head = false;
+                events.push(Event::Html("</tr></thead><tbody>".into()));
+            }
+            Event::Start(Tag::TableRow) => {
+                table_column = 0;
+                events.push(Event::Html("<tr>".into()));
+            }
+            Event::End(TagEnd::TableRow) => {
+                events.push(Event::Html("</tr>".into()));
+            }
+            Event::Start(Tag::TableCell) => {
+                let cell = if in_table_head { "th" } else { "td" };
+                let html = match table_alignments.get(table_column) {
+                    Some(Alignment::Left) => format!(r#"<{} class="align-left">"#, cell),
+                    Some(Alignment::Center) => format!(r#"<{} class="align-center">"#, cell),
+                    Some(Alignment::Right) => format!(r#"<{} class="align-right">"#, cell),
+                    _ => format!("<{}>", cell),
+                };
+                events.push(Event::Html(html.into()));
+            }
+            Event::End(TagEnd::TableCell) => {
+                let cell = if in_table_head { "th" } else { "td" };
+                events.push(Event::Html(format!("</{}>", cell).into()));
+                table_column += 1;
+            }
+            Event::Start(Tag::BlockQuote(kind)) => {
+                // `> [!NOTE] Title` turns the blockquote into a callout.
+                let mut callout = None;
+                let starts_with_paragraph = matches!(parser.peek(), Some(Event::Start(Tag::Paragraph)));
+                if starts_with_paragraph {
+                    parser.next();
+                    if let Some(Event::Text(text)) = parser.peek() {
+                        callout = parse_callout(text);
+                    }
+                }
+
+                let Some(callout) = callout else {
+                    events.push(Event::Start(Tag::BlockQuote(kind)));
+                    if starts_with_paragraph {
+                        events.push(Event::Start(Tag::Paragraph));
+                    }
+                    blockquotes.push(None);
+                    continue;
+                };
+                parser.next();
+
+                // The rest of the marker's line is the title; the body may carry on in the
+                // same paragraph after a line break.
+                let mut title_events = Vec::new();
+                let mut body_continues = false;
+                for event in parser.by_ref() {
+                    match event {
+                        Event::SoftBreak | Event::HardBreak => {
+                            body_continues = true;
+                            break;
+                        }
+                        Event::End(TagEnd::Paragraph) => break,
+                        event => title_events.push(event),
+                    }
+                }
+
+                let (class, icon) = callout_style(&callout.kind);
+                let mut title_html = htmlescape::encode_minimal(&callout.title);
+                if !title_events.is_empty() {
+                    html::push_html(&mut title_html, title_events.into_iter());
+                }
+                if title_html.trim().is_empty() {
+                    let mut chars = callout.kind.chars();
+                    title_html = chars
+                        .next()
+                        .map(|first| first.to_uppercase().chain(chars).collect())
+                        .unwrap_or_default();
+                }
+
+                let header = format!(
+                    r#"<i class="ph {icon} callout-icon"></i><span class="callout-title-text">{title_html}</span>"#
+                );
+                let html = match callout.open {
+                    None => {
+                        blockquotes.push(Some("</div></div>"));
+                        format!(
+                            r#"<div class="callout callout-{class}"><div class="callout-title">{header}</div><div class="callout-content">"#
+                        )
+                    }
+                    Some(open) => {
+                        blockquotes.push(Some("</div></details>"));
+                        format!(
+                            r#"<details class="callout callout-{class}"{}><summary class="callout-title">{header}<i class="ph ph-caret-down callout-fold"></i></summary><div class="callout-content">"#,
+                            if open { " open" } else { "" }
+                        )
+                    }
+                };
+                events.push(Event::Html(html.into()));
+                if body_continues {
+                    events.push(Event::Start(Tag::Paragraph));
+                }
+            }
+            Event::End(TagEnd::BlockQuote(kind)) => {
+                match blockquotes.pop().flatten() {
+                    Some(closing) => events.push(Event::Html(closing.into())),
+                    None => events.push(Event::End(TagEnd::BlockQuote(kind))),
+                }
+            }
+            Event::Start(Tag::Link { link_type: LinkType::WikiLink { .. }, dest_url, .. }) => {
+                let (open, close) = wikilink_html(&dest_url, state.content);
+                wikilink_close = Some(close);
+                match current_heading.as_mut() {
+                    Some((_, inner_events)) => inner_events.push(Event::Html(open.into())),
+                    None => events.push(Event::Html(open.into())),
+                }
+            }
+            Event::End(TagEnd::Link) if wikilink_close.is_some() => {
+                let close = Event::Html(wikilink_close.take().unwrap_or_default().into());
+                match current_heading.as_mut() {
+                    Some((_, inner_events)) => inner_events.push(close),
+                    None => events.push(close),
+                }
+            }
+            Event::Start(Tag::Image { link_type: LinkType::WikiLink { .. }, ref dest_url, .. })
+                if current_heading.is_none() && !in_code_block =>
+            {
+                let mut referenced: Vec<&str> = Vec::new();
+                for event in &events {
+                    if let Event::FootnoteReference(label) = event
+                        && !referenced.contains(&label.as_ref())
+                    {
+                        referenced.push(label);
+                    }
+                }
+                let referenced = referenced.len();
+                let taken: usize = reserved_notes.iter().map(|(_, taken)| taken).sum();
+                state.footnotes = first_note - 1 + referenced + taken;
+                let embedded = embed_note(dest_url, highlighter, options, state);
+                reserved_notes.push((referenced, state.footnotes - (first_note - 1 + referenced + taken)));
+
+                match embedded {
+                    Some(html) => {
+                        // The alt text events up to the end of the image are not needed.
+                        for event in parser.by_ref() {
+                            if matches!(event, Event::End(TagEnd::Image)) {
+                                break;
+                            }
+                        }
+                        // An embed on a line of its own should not end up inside a `<p>`.
+                        if matches!(events.last(), Some(Event::Start(Tag::Paragraph)))
+                            && matches!(parser.peek(), Some(Event::End(TagEnd::Paragraph)))
+                        {
+                            events.pop();
+                            parser.next();
+                        }
+                        events.push(Event::Html(html.into()));
+                    }
+                    None => events.push(event),
+                }
+            }
+            Event::TaskListMarker(checked) => {
+                let html = if checked {
+                    r#"<input type="checkbox" class="task-checkbox" checked disabled>"#
+                } else {
+                    r#"<input type="checkbox" class="task-checkbox" disabled>"#
+                };
+                events.push(Event::Html(html.into()));
+            }
             _ => {
                 if in_code_block {
                     if let Event::Text(text) = event {
@@ -264,10 +1262,193 @@ pub fn markdown_to_html(content: &str, highlighter: &Mutex<Highlighter>) -> (Str
     }
 
     let mut html_output = String::new();
+    let notes = NoteNumbering { prefix: &state.note_prefix, first: first_note, reserved: &reserved_notes };
+    let (events, numbered) = collect_footnotes(events, options.notes, &notes);
+    state.footnotes = first_note - 1 + numbered + reserved_notes.iter().map(|(_, taken)| taken).sum::<usize>();
     html::push_html(&mut html_output, events.into_iter());
     (html_output, headings)
 }
 
+// Where the footnotes of one page or embedded note start counting from, the numbers its
+// embedded notes took, and what their ids are prefixed with.
+struct NoteNumbering<'a> {
+    prefix: &'a str,
+    first: usize,
+    reserved: &'a [(usize, usize)],
+}
+
+impl NoteNumbering<'_> {
+    // Displayed number of the note at `index` in order of first reference.
+    fn number(&self, index: usize) -> usize {
+        let taken: usize = self.reserved.iter().filter(|(before, _)| *before <= index).map(|(_, taken)| taken).sum();
+        self.first + index + taken
+    }
+
+    fn slug(&self, label: &str) -> String {
+        format!("{}{}", self.prefix, slugify(label))
+    }
+}
+
+/// Pulls footnote definitions out of the flow and numbers them in the order they are first
+/// referenced. With `NoteStyle::Bottom` they are appended as a list, each with links back to
+/// every place it is referenced; with `NoteStyle::Side` each one is placed right after its
+/// first reference as a sidenote. Definitions that are never referenced are dropped.
+/// Returns the events and how many notes were numbered.
+fn collect_footnotes<'a>(events: Vec<Event<'a>>, style: NoteStyle, notes: &NoteNumbering) -> (Vec<Event<'a>>, usize) {
+    // References may come before their definition, so split the definitions out first.
+    let mut body = Vec::new();
+    let mut definitions: HashMap<String, Vec<Event>> = HashMap::new();
+    let mut current_definition: Option<(String, Vec<Event>)> = None;
+    for event in events {
+        match event {
+            Event::Start(Tag::FootnoteDefinition(label)) => {
+                current_definition = Some((label.to_string(), Vec::new()));
+            }
+            Event::End(TagEnd::FootnoteDefinition) => {
+                if let Some((label, definition)) = current_definition.take() {
+                    definitions.insert(label, definition);
+                }
+            }
+            event => match current_definition.as_mut() {
+                Some((_, definition)) => definition.push(event),
+                None => body.push(event),
+            },
+        }
+    }
+
+    // Labels in order of first reference, with how often each was referenced.
+    let mut references: Vec<(String, usize)> = Vec::new();
+
+    if style == NoteStyle::Side {
+        let mut output = Vec::with_capacity(body.len());
+        for event in body {
+            match event {
+                Event::FootnoteReference(label) if definitions.contains_key(label.as_ref()) => {
+                    let (index, count) = count_reference(&mut references, &label);
+                    let number = notes.number(index);
+                    let id = format!("sn-{}", notes.slug(&label));
+                    output.push(Event::Html(format!(
+                        r#"<label for="{id}" class="sidenote-number">{number}</label>"#
+                    ).into()));
+                    // Later references to the same note toggle the sidenote shown at the first.
+                    if count == 1 {
+                        output.push(Event::Html(format!(
+                            r#"<input type="checkbox" id="{id}" class="sidenote-toggle"><span class="sidenote"><span class="sidenote-label">{number}</span> "#
+                        ).into()));
+                        output.extend(inline_note(definitions[label.as_ref()].clone(), &references, notes));
+                        output.push(Event::Html("</span>".into()));
+                    }
+                }
+                event => output.push(event),
+            }
+        }
+        return (output, references.len());
+    }
+
+    let mut output = link_references(body, &definitions, &mut references, notes);
+
+    // Footnotes referenced only from other footnotes are appended to `references` as the
+    // definitions are linked, so the list grows while it is walked.
+    let mut list = Vec::new();
+    let mut i = 0;
+    while i < references.len() {
+        let label = references[i].0.clone();
+        if let Some(definition) = definitions.remove(&label) {
+            list.push((label, link_references(definition, &definitions, &mut references, notes)));
+        }
+        i += 1;
+    }
+    if list.is_empty() {
+        return (output, references.len());
+    }
+
+    output.push(Event::Html(r#"<section class="footnotes"><ol>"#.into()));
+    for (label, mut definition) in list {
+        let slug = notes.slug(&label);
+        let index = references.iter().position(|(l, _)| *l == label).unwrap_or_default();
+        let count = references.get(index).map_or(1, |(_, count)| *count);
+        let backrefs: String = (1..=count)
+            .map(|n| {
+                let marker = if count > 1 { format!("<sup>{}</sup>", n) } else { String::new() };
+                format!(r##" <a href="#fnref-{slug}-{n}" class="footnote-backref" aria-label="Back to reference">↩{marker}</a>"##)
+            })
+            .collect();
+
+        // Keep the back-links inside the definition's last paragraph when there is one.
+        let backrefs = Event::Html(backrefs.into());
+        match definition.last() {
+            Some(Event::End(TagEnd::Paragraph)) => definition.insert(definition.len() - 1, backrefs),
+            _ => definition.push(backrefs),
+        }
+
+        // Embedded notes take numbers in between, so the list cannot count by itself.
+        output.push(Event::Html(format!(r#"<li id="fn-{}" value="{}">"#, slug, notes.number(index)).into()));
+        output.extend(definition);
+        output.push(Event::Html("</li>".into()));
+    }
+    output.push(Event::Html("</ol></section>".into()));
+    (output, references.len())
+}
+
+// Position of the footnote `label` in order of first reference and how many times it has
+// been referenced so far.
+fn count_reference(references: &mut Vec<(String, usize)>, label: &str) -> (usize, usize) {
+    match references.iter().position(|(l, _)| l == label) {
+        Some(i) => {
+            references[i].1 += 1;
+            (i, references[i].1)
+        }
+        None => {
+            references.push((label.to_string(), 1));
+            (references.len() - 1, 1)
+        }
+    }
+}
+
+// Turns footnote references into superscript links to the list at the bottom of the page.
+fn link_references<'a>(
+    events: Vec<Event<'a>>,
+    definitions: &HashMap<String, Vec<Event>>,
+    references: &mut Vec<(String, usize)>,
+    notes: &NoteNumbering,
+) -> Vec<Event<'a>> {
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::FootnoteReference(label) if definitions.contains_key(label.as_ref()) || references.iter().any(|(l, _)| *l == *label) => {
+                let (index, count) = count_reference(references, &label);
+                let number = notes.number(index);
+                let slug = notes.slug(&label);
+                Event::Html(format!(
+                    r##"<sup class="footnote-ref" id="fnref-{slug}-{count}"><a href="#fn-{slug}">{number}</a></sup>"##
+                ).into())
+            }
+            event => event,
+        })
+        .collect()
+}
+
+// A sidenote sits inside the paragraph of its reference, so its own paragraphs become line
+// breaks. References nested in it show the number of an already placed note, if any.
+fn inline_note<'a>(definition: Vec<Event<'a>>, references: &[(String, usize)], notes: &NoteNumbering) -> Vec<Event<'a>> {
+    let mut events: Vec<Event> = definition
+        .into_iter()
+        .filter_map(|event| match event {
+            Event::Start(Tag::Paragraph) => None,
+            Event::FootnoteReference(label) => {
+                let index = references.iter().position(|(l, _)| *l == *label)?;
+                Some(Event::Html(format!("<sup>{}</sup>", notes.number(index)).into()))
+            }
+            Event::End(TagEnd::Paragraph) => Some(Event::Html("<br>".into())),
+            event => Some(event),
+        })
+        .collect();
+    if matches!(events.last(), Some(Event::Html(html)) if html.as_ref() == "<br>") {
+        events.pop();
+    }
+    events
+}
+
 pub fn extract_frontmatter(content: &str) -> (JsonValue, &str) {
     if let Some(caps) = FRONTMATTER_REGEX.captures(content) {
         let yaml_str = caps.get(1).unwrap().as_str();
@@ -280,4 +1461,75 @@ pub fn extract_frontmatter(content: &str) -> (JsonValue, &str) {
     } else {
         (JsonValue::Null, content)
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Content directory holding `files` and its index, removed again when dropped. The
+    // global content index is left alone.
+    struct TestContent {
+        dir: PathBuf,
+        index: ContentIndex,
+    }
+
+    impl TestContent {
+        fn new(name: &str, files: &[(&str, &str)]) -> Self {
+            let dir = std::env::temp_dir().join(format!("personal-{}-{}", name, std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            for (file, text) in files {
+                fs::write(dir.join(file), text).unwrap();
+            }
+            let index = ContentIndex::build(&dir).unwrap();
+            TestContent { dir, index }
+        }
+
+        fn render(&self, markdown: &str, notes: NoteStyle) -> String {
+            let options = MarkdownOptions { notes, ..MarkdownOptions::default() };
+            markdown_to_html_with(&self.index, markdown, &Mutex::new(Highlighter::new()), &options).html
+        }
+    }
+
+    impl Drop for TestContent {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn embedded_footnotes_do_not_clash_with_the_page() {
+        let content = TestContent::new("embed-footnotes", &[(
+            "note.md",
+            "---\ntitle: note\n---\nEmbedded[^a].\n\n[^a]: Embedded footnote.\n",
+        )]);
+        let page = "Page[^a].\n\n![[note]]\n\nAgain[^b].\n\n[^a]: Page footnote.\n[^b]: Second page footnote.\n";
+
+        let html = content.render(page, NoteStyle::Bottom);
+        assert_eq!(html.matches(r#"id="fn-a" value="1""#).count(), 1);
+        assert_eq!(html.matches(r#"id="fn-b" value="3""#).count(), 1);
+        assert_eq!(html.matches(r#"id="fnref-a-1""#).count(), 1);
+        assert_eq!(html.matches(r#"id="fn-note-a" value="2""#).count(), 1);
+        assert!(html.contains(r##"href="#fnref-note-a-1""##));
+        // Numbers run on through the embedded note in reading order.
+        assert!(html.contains(r##"<a href="#fn-a">1</a>"##));
+        assert!(html.contains(r##"<a href="#fn-note-a">2</a>"##));
+        assert!(html.contains(r##"<a href="#fn-b">3</a>"##));
+
+        let html = content.render(page, NoteStyle::Side);
+        assert_eq!(html.matches(r#"id="sn-a""#).count(), 1);
+        assert_eq!(html.matches(r#"id="sn-note-a""#).count(), 1);
+    }
+
+    #[test]
+    fn diff_headers_only_come_before_the_first_hunk() {
+        let diff = "--- a/schema.sql\n+++ b/schema.sql\n@@ -1,3 +1,2 @@\n--- users\n+-- accounts\n select 1;\n";
+        let (lines, added, deleted) = diff_lines(&Mutex::new(Highlighter::new()), None, diff);
+
+        let code: Vec<bool> = lines.iter().map(|line| line.code).collect();
+        assert_eq!(code, [false, false, false, true, true, true]);
+        assert_eq!(deleted, HashSet::from([4]));
+        assert_eq!(added, HashSet::from([5]));
+        assert_eq!(lines[3].html, "-- users");
+        assert_eq!(lines[4].html, "-- accounts");
+    }
+}
@@ -0,0 +1,55 @@
+use actix_web::dev::ResourceDef;
+use actix_web::web;
+use once_cell::sync::Lazy;
+
+use crate::handlers::{
+    broken_links, generate_og_image, generate_tweet_image, generate_web_og, graph, health_check, highlight_stylesheet,
+    index, page_graph, projects, resume, search, search_index_json, search_page, search_suggest, unknown_languages,
+};
+use crate::rss::rss_feed;
+
+// A table of GET routes and a function registering them, so that the link checker knows
+// every path the server answers without keeping a list of its own.
+macro_rules! routes {
+    ($table:ident, $register:ident { $($path:literal => $handler:path),* $(,)? }) => {
+        pub const $table: &[&str] = &[$($path),*];
+
+        pub fn $register(cfg: &mut web::ServiceConfig) {
+            $(cfg.service(web::resource($path).route(web::get().to($handler)));)*
+        }
+    };
+}
+
+routes!(ROUTES, register_routes {
+    // Before `/static`, which would otherwise look for the file.
+    "/static/highlight-{theme}.css" => highlight_stylesheet,
+    "/" => index,
+    "/stuff" => projects,
+    "/resume" => resume,
+    "/search" => search_page,
+    "/og/content/{path:.*}" => generate_og_image,
+    "/og/web/{path:.*}" => generate_web_og,
+    "/tweet/{path:.*}" => generate_tweet_image,
+    "/rss.xml" => rss_feed,
+    "/api/search" => search,
+    "/api/search/suggest" => search_suggest,
+    "/search-index.json" => search_index_json,
+    "/api/graph" => graph,
+    "/api/graph/{path:.*}" => page_graph,
+    "/health" => health_check,
+});
+
+// Reports that list drafts and source files; only served outside production.
+routes!(ADMIN_ROUTES, register_admin_routes {
+    "/admin/links" => broken_links,
+    "/admin/languages" => unknown_languages,
+});
+
+static ROUTE_PATTERNS: Lazy<Vec<ResourceDef>> =
+    Lazy::new(|| ROUTES.iter().chain(ADMIN_ROUTES).map(|route| ResourceDef::new(*route)).collect());
+
+/// Whether `path`, without its leading slash, is answered by one of the routes above.
+pub fn is_route(path: &str) -> bool {
+    let path = format!("/{}", path);
+    ROUTE_PATTERNS.iter().any(|pattern| pattern.is_match(&path))
+}
@@ -1,200 +1,893 @@
-use std::fs;
-use std::path::Path;
 use serde::{Serialize, Deserialize};
 use once_cell::sync::Lazy;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::RwLock;
+use std::time::Instant;
 use rayon::prelude::*;
-use crate::markdown::extract_frontmatter;
+use crate::content::{ContentEntry, ContentIndex, CONTENT_INDEX};
+use crate::markdown::{extract_text_spans, SpanKind, TextSpan};
+
+// BM25 parameters: term frequency saturation and document length normalisation.
+const K1: f32 = 1.2;
+const B: f32 = 0.75;
+// Weight of a single occurrence in the title or a heading relative to one in the body.
+const TITLE_BOOST: f32 = 4.0;
+const HEADING_BOOST: f32 = 2.0;
+// Query terms also match longer terms they are a prefix of, and, when they do not occur
+// anywhere, terms within a small edit distance. Both count for less than an exact match.
+const PREFIX_WEIGHT: f32 = 0.7;
+const FUZZY_WEIGHT: f32 = 0.5;
+const MIN_PREFIX_LEN: usize = 3;
+const MAX_PREFIX_EXPANSIONS: usize = 10;
+const MAX_SUGGESTIONS: usize = 8;
+// Characters of text kept on either side of a hit in a snippet.
+pub const DEFAULT_SNIPPET_WINDOW: usize = 40;
+pub const MAX_SNIPPET_WINDOW: usize = 200;
+// Results per page and snippets per result, with upper bounds for API callers.
+pub const DEFAULT_LIMIT: usize = 10;
+pub const MAX_LIMIT: usize = 50;
+pub const DEFAULT_MAX_CONTEXTS: usize = 3;
+pub const MAX_CONTEXTS: usize = 20;
+// Bump whenever the tokenizer, the span extraction or the serialized layout changes, so
+// that index files written by an older build are rebuilt instead of trusted.
+pub const INDEX_FORMAT_VERSION: u32 = 4;
+
+/// Where the index is cached between runs: `$SEARCH_INDEX_PATH`, or `search-index.json` in
+/// the working directory.
+pub static SEARCH_INDEX_PATH: Lazy<PathBuf> = Lazy::new(|| {
+    std::env::var_os("SEARCH_INDEX_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("search-index.json"))
+});
+
+lazy_static! {
+    // Combining marks belong to the word they follow, or Devanagari words would fall apart.
+    static ref WORD_REGEX: Regex = Regex::new(r"[\p{Alphabetic}\p{M}\p{Nd}]+").unwrap();
+    static ref QUERY_PART_REGEX: Regex = Regex::new(r#"(-)?(?:([a-z]+):)?(?:"([^"]*)"?|(\S+))"#).unwrap();
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct IndexedDocument {
     title: String,
     url: String,
-    content: String,
-    lowercase_content: String,
+    section: String,
+    category: Option<String>,
+    spans: Vec<TextSpan>,
+    length: usize,
+}
+
+impl IndexedDocument {
+    fn terms(&self) -> impl Iterator<Item = String> + '_ {
+        tokenize(&self.title).chain(self.spans.iter().flat_map(|span| tokenize(&span.text)))
+    }
 }
 
-static SEARCH_INDEX: Lazy<RwLock<Vec<IndexedDocument>>> = Lazy::new(|| RwLock::new(Vec::new()));
+// Most terms only occur in one kind of text, so zero counts are left out of the index file.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct TermFrequency {
+    #[serde(default, skip_serializing_if = "is_zero")]
+    body: u32,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    code: u32,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    title: u32,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    heading: u32,
+}
 
-pub fn initialize_search_index(base_path: &Path) -> std::io::Result<()> {
-    let mut documents = Vec::new();
-    index_directory(base_path, base_path, &mut documents)?;
-    
-    let mut index = SEARCH_INDEX.write().unwrap();
-    *index = documents;
-    
-    Ok(())
+fn is_zero(count: &u32) -> bool {
+    *count == 0
 }
 
-fn index_directory(base_path: &Path, current_dir: &Path, documents: &mut Vec<IndexedDocument>) -> std::io::Result<()> {
-    for entry in fs::read_dir(current_dir)? {
-        let entry = entry?;
-        let path = entry.path();
-        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
-        
-        if file_name.starts_with('.') {
-            continue;
+impl TermFrequency {
+    fn weighted(&self, code: CodeFilter) -> f32 {
+        let prose = self.body as f32 + TITLE_BOOST * self.title as f32 + HEADING_BOOST * self.heading as f32;
+        match code {
+            CodeFilter::Any => prose + self.code as f32,
+            CodeFilter::Only => self.code as f32,
+            CodeFilter::Exclude => prose,
         }
-        
-        if path.is_dir() {
-            index_directory(base_path, &path, documents)?;
-        } else if path.extension().map_or(false, |ext| ext == "md") {
-            index_file(base_path, &path, documents)?;
+    }
+}
+
+/// Whether code blocks take part in a search (`is:code` / `-is:code`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum CodeFilter {
+    #[default]
+    Any,
+    Only,
+    Exclude,
+}
+
+impl CodeFilter {
+    fn allows(&self, kind: SpanKind) -> bool {
+        match self {
+            CodeFilter::Any => true,
+            CodeFilter::Only => kind == SpanKind::Code,
+            CodeFilter::Exclude => kind != SpanKind::Code,
         }
     }
-    
-    Ok(())
 }
 
-fn index_file(base_path: &Path, file_path: &Path, documents: &mut Vec<IndexedDocument>) -> std::io::Result<()> {
-    let content = fs::read_to_string(file_path)?;
-    
-    let (frontmatter, body) = extract_frontmatter(&content);
-    
-    let title = if let serde_json::Value::Object(ref map) = frontmatter {
-        map.get("title")
-            .and_then(|v| v.as_str())
-            .map(|s| s.to_string())
-            .unwrap_or_else(|| {
-                file_path
-                    .file_stem()
-                    .unwrap_or_default()
-                    .to_string_lossy()
-                    .into_owned()
-            })
-            .to_string()
-    } else {
-        file_path
-            .file_stem()
-            .unwrap_or_default()
-            .to_string_lossy()
-            .into_owned()
-    };
-    
-    // Skip draft posts
-    if let serde_json::Value::Object(ref map) = frontmatter {
-        if let Some(serde_json::Value::Bool(is_draft)) = map.get("draft") {
-            if *is_draft {
-                return Ok(());
+/// Inverted index from terms to the documents (by URL) that contain them.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SearchIndex {
+    documents: HashMap<String, IndexedDocument>,
+    postings: BTreeMap<String, HashMap<String, TermFrequency>>,
+    total_length: usize,
+}
+
+impl SearchIndex {
+    /// Indexes every published page in `content`.
+    pub fn build(content: &ContentIndex) -> Self {
+        let mut index = SearchIndex::default();
+        for entry in content.published() {
+            index.insert(index_entry(entry));
+        }
+        index
+    }
+
+    fn insert(&mut self, document: IndexedDocument) {
+        self.remove(&document.url.clone());
+
+        for term in tokenize(&document.title) {
+            self.postings.entry(term).or_default().entry(document.url.clone()).or_default().title += 1;
+        }
+        for span in &document.spans {
+            for term in tokenize(&span.text) {
+                let frequency = self.postings.entry(term).or_default().entry(document.url.clone()).or_default();
+                match span.kind {
+                    SpanKind::Text => frequency.body += 1,
+                    SpanKind::Heading => frequency.heading += 1,
+                    SpanKind::Code => frequency.code += 1,
+                }
             }
         }
+
+        self.total_length += document.length;
+        self.documents.insert(document.url.clone(), document);
+    }
+
+    fn remove(&mut self, url: &str) {
+        let Some(document) = self.documents.remove(url) else {
+            return;
+        };
+
+        for term in document.terms() {
+            if let Some(posting) = self.postings.get_mut(&term) {
+                posting.remove(url);
+                if posting.is_empty() {
+                    self.postings.remove(&term);
+                }
+            }
+        }
+
+        self.total_length -= document.length;
+    }
+
+    fn average_length(&self) -> f32 {
+        if self.documents.is_empty() {
+            return 0.0;
+        }
+        self.total_length as f32 / self.documents.len() as f32
+    }
+
+    /// Terms from the index that `term` stands for, with how much each counts.
+    fn expand(&self, term: &str) -> Vec<(String, f32)> {
+        let mut expansions = Vec::new();
+        let exact = self.postings.contains_key(term);
+        if exact {
+            expansions.push((term.to_string(), 1.0));
+        }
+
+        if term.chars().count() >= MIN_PREFIX_LEN {
+            expansions.extend(
+                self.postings
+                    .range::<str, _>((std::ops::Bound::Excluded(term), std::ops::Bound::Unbounded))
+                    .take_while(|(candidate, _)| candidate.starts_with(term))
+                    .take(MAX_PREFIX_EXPANSIONS)
+                    .map(|(candidate, _)| (candidate.clone(), PREFIX_WEIGHT)),
+            );
+        }
+
+        let max_distance = max_typos(term);
+        if !exact && max_distance > 0 {
+            for candidate in self.postings.keys() {
+                if expansions.iter().any(|(t, _)| t == candidate) {
+                    continue;
+                }
+                if let Some(distance) = edit_distance(term, candidate, max_distance) {
+                    expansions.push((candidate.clone(), FUZZY_WEIGHT / distance as f32));
+                }
+            }
+        }
+
+        expansions
+    }
+
+    /// BM25 score of every document matching at least one of the term groups. Each group is
+    /// one query term with its expansions; a document scores by its best expansion per group.
+    fn score(&self, groups: &[Vec<(String, f32)>], code: CodeFilter) -> HashMap<&str, f32> {
+        let total_documents = self.documents.len() as f32;
+        let average_length = self.average_length().max(1.0);
+        let mut scores: HashMap<&str, f32> = HashMap::new();
+
+        for group in groups {
+            let mut best: HashMap<&str, f32> = HashMap::new();
+            for (term, weight) in group {
+                let Some(posting) = self.postings.get(term) else {
+                    continue;
+                };
+                let matching = posting.len() as f32;
+                let idf = (1.0 + (total_documents - matching + 0.5) / (matching + 0.5)).ln();
+
+                for (url, frequency) in posting {
+                    let Some(document) = self.documents.get(url) else {
+                        continue;
+                    };
+                    let tf = frequency.weighted(code);
+                    if tf == 0.0 {
+                        continue;
+                    }
+                    let normalisation = 1.0 - B + B * document.length as f32 / average_length;
+                    let score = weight * idf * tf * (K1 + 1.0) / (tf + K1 * normalisation);
+                    let entry = best.entry(document.url.as_str()).or_default();
+                    *entry = entry.max(score);
+                }
+            }
+            for (url, score) in best {
+                *scores.entry(url).or_default() += score;
+            }
+        }
+
+        scores
+    }
+}
+
+fn max_typos(term: &str) -> usize {
+    match term.chars().count() {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+/// Optimal string alignment distance (Levenshtein plus adjacent transpositions),
+/// or `None` once it is known to exceed `max`.
+fn edit_distance(a: &str, b: &str, max: usize) -> Option<usize> {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
+
+    let mut previous_previous: Vec<usize> = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut current = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (previous[j] + 1).min(current[j - 1] + 1).min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(previous_previous[j - 2] + 1);
+            }
+        }
+        if current.iter().all(|&d| d > max) {
+            return None;
+        }
+        previous_previous = std::mem::replace(&mut previous, current);
+    }
+
+    let distance = previous[b.len()];
+    (distance <= max).then_some(distance)
+}
+
+static SEARCH_INDEX: Lazy<RwLock<SearchIndex>> = Lazy::new(|| RwLock::new(SearchIndex::default()));
+
+/// Splits text into lowercase alphanumeric terms.
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    WORD_REGEX.find_iter(text).map(|token| token.as_str().to_lowercase())
+}
+
+/// On-disk and over-the-wire form of the search index.
+#[derive(Serialize, Deserialize)]
+struct IndexFile<I> {
+    version: u32,
+    fingerprint: String,
+    index: I,
+}
+
+/// Loads the index from `SEARCH_INDEX_PATH` when it was built by this version from the
+/// current content, and otherwise indexes everything again and rewrites the file.
+pub fn initialize_search_index() {
+    let content = CONTENT_INDEX.read().unwrap();
+    let fingerprint = content_fingerprint(content.published());
+
+    if let Some(index) = load_index_file(&SEARCH_INDEX_PATH, &fingerprint) {
+        *SEARCH_INDEX.write().unwrap() = index;
+        return;
+    }
+
+    let index = SearchIndex::build(&content);
+    let file = IndexFile { version: INDEX_FORMAT_VERSION, fingerprint, index: &index };
+    if let Err(e) = serde_json::to_vec(&file).map_err(std::io::Error::from).and_then(|json| write_atomically(&SEARCH_INDEX_PATH, &json)) {
+        eprintln!("Failed to write {}: {}", SEARCH_INDEX_PATH.display(), e);
+    }
+
+    *SEARCH_INDEX.write().unwrap() = index;
+}
+
+// Writes next to `path` and renames over it, so a crash mid-write cannot leave a truncated
+// index for the next start to trip over.
+fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(format!(".{}.tmp", std::process::id()));
+    fs::write(&temp, contents)?;
+    fs::rename(&temp, path).inspect_err(|_| {
+        let _ = fs::remove_file(&temp);
+    })
+}
+
+fn load_index_file(path: &Path, fingerprint: &str) -> Option<SearchIndex> {
+    let json = fs::read(path).ok()?;
+    let file: IndexFile<SearchIndex> = match serde_json::from_slice(&json) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("Ignoring unreadable {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    (file.version == INDEX_FORMAT_VERSION && file.fingerprint == fingerprint).then_some(file.index)
+}
+
+// Hash of everything the indexer reads, so an index file can tell whether it is stale.
+fn content_fingerprint<'a>(entries: impl Iterator<Item = &'a ContentEntry>) -> String {
+    let mut hasher = Sha256::new();
+    for entry in entries {
+        for field in [&entry.page.url, &entry.page.title, entry.page.category.as_deref().unwrap_or(""), &entry.body] {
+            hasher.update((field.len() as u64).to_le_bytes());
+            hasher.update(field.as_bytes());
+        }
+    }
+    hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// The current index, serialized the same way as `SEARCH_INDEX_PATH`, for client-side search.
+pub fn serialize_search_index() -> serde_json::Result<String> {
+    let content = CONTENT_INDEX.read().unwrap();
+    let index = SEARCH_INDEX.read().unwrap();
+    serde_json::to_string(&IndexFile {
+        version: INDEX_FORMAT_VERSION,
+        fingerprint: content_fingerprint(content.published()),
+        index: &*index,
+    })
+}
+
+/// Brings the documents at or below `url` back in line with the content index.
+pub fn refresh_documents(url: &str) {
+    let prefix = format!("{}/", url);
+    let content = CONTENT_INDEX.read().unwrap();
+    let mut index = SEARCH_INDEX.write().unwrap();
+
+    let stale: Vec<String> = index
+        .documents
+        .keys()
+        .filter(|doc_url| *doc_url == url || doc_url.starts_with(&prefix))
+        .cloned()
+        .collect();
+    for doc_url in stale {
+        index.remove(&doc_url);
+    }
+
+    for entry in content
+        .published()
+        .filter(|entry| entry.page.url == url || entry.page.url.starts_with(&prefix))
+    {
+        index.insert(index_entry(entry));
+    }
+}
+
+fn index_entry(entry: &ContentEntry) -> IndexedDocument {
+    let spans = extract_text_spans(&entry.body);
+    let length = spans.iter().map(|span| tokenize(&span.text).count()).sum();
+
+    IndexedDocument {
+        title: entry.page.title.clone(),
+        url: entry.page.url.clone(),
+        section: entry.page.section().to_string(),
+        category: entry.page.category.as_ref().map(|c| c.to_lowercase()),
+        spans,
+        length,
     }
-    let rel_path = file_path
-        .strip_prefix(base_path)
-        .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "Failed to get relative path"))?;
-    
-    let url = rel_path
-        .with_extension("")
-        .to_string_lossy()
-        .replace('\\', "/");
-    
-    documents.push(IndexedDocument {
-        title,
-        url,
-        content: body.to_string(),
-        lowercase_content: body.to_lowercase(),
-    });
-    
-    Ok(())
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchResult {
     pub title: String,
     pub url: String,  
+    pub score: f32,
     pub contexts: Vec<ContextMatch>,
+    /// Snippets found before `contexts` was cut down to `max_contexts`.
+    pub total_contexts: usize,
+}
+
+/// Which slice of the results to return and how much of each.
+#[derive(Debug, Clone, Copy)]
+pub struct SearchOptions {
+    pub limit: usize,
+    pub offset: usize,
+    pub max_contexts: usize,
+    pub window: usize,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            limit: DEFAULT_LIMIT,
+            offset: 0,
+            max_contexts: DEFAULT_MAX_CONTEXTS,
+            window: DEFAULT_SNIPPET_WINDOW,
+        }
+    }
+}
+
+/// One page of search results plus what is needed to ask for the next one.
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchPage {
+    pub query: String,
+    pub total: usize,
+    pub took_ms: f64,
+    pub offset: usize,
+    pub limit: usize,
+    pub results: Vec<SearchResult>,
 }
 
+/// A snippet of a page around one or more hits.
+///
+/// `matches` are character (not byte) ranges into `context`; `html` is `context`, escaped,
+/// with every hit wrapped in `<mark>`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContextMatch {
     pub context: String,
+    pub html: String,
+    pub matches: Vec<MatchRange>,
     pub url: String,
 }
 
-pub fn search_content(query: &str) -> Vec<SearchResult> {
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MatchRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A search query broken down into its parts.
+///
+/// `rust "game engine" -unity in:blog/devlogs title:zig category:guide`
+#[derive(Debug, Default)]
+struct ParsedQuery {
+    terms: Vec<String>,
+    phrases: Vec<Vec<String>>,
+    excluded_terms: Vec<String>,
+    excluded_phrases: Vec<Vec<String>>,
+    sections: Vec<String>,
+    titles: Vec<String>,
+    categories: Vec<String>,
+    excluded_sections: Vec<String>,
+    excluded_titles: Vec<String>,
+    excluded_categories: Vec<String>,
+    code: CodeFilter,
+}
+
+impl ParsedQuery {
+    fn parse(query: &str) -> Self {
+        let mut parsed = ParsedQuery::default();
+
+        for caps in QUERY_PART_REGEX.captures_iter(query) {
+            let negated = caps.get(1).is_some();
+            let field = caps.get(2).map(|m| m.as_str());
+            let quoted = caps.get(3).map(|m| m.as_str());
+            let value = quoted.or(caps.get(4).map(|m| m.as_str())).unwrap_or_default();
+            let tokens: Vec<String> = tokenize(value).collect();
+
+            if field == Some("is") && value.eq_ignore_ascii_case("code") {
+                parsed.code = if negated { CodeFilter::Exclude } else { CodeFilter::Only };
+                continue;
+            }
+
+            let filter = match field {
+                Some("in") => Some((&mut parsed.sections, &mut parsed.excluded_sections, value.trim_matches('/'))),
+                Some("title") => Some((&mut parsed.titles, &mut parsed.excluded_titles, value.trim())),
+                Some("category") => Some((&mut parsed.categories, &mut parsed.excluded_categories, value.trim())),
+                _ => None,
+            };
+            match filter {
+                Some((included, excluded, value)) => {
+                    if !value.is_empty() {
+                        let target = if negated { excluded } else { included };
+                        target.push(value.to_lowercase());
+                    }
+                }
+                None => {
+                    // Unknown prefixes such as `http:` are just part of the text.
+                    let tokens: Vec<String> = match field {
+                        Some(_) => tokenize(caps.get(0).unwrap().as_str()).collect(),
+                        None => tokens,
+                    };
+                    if tokens.is_empty() {
+                        continue;
+                    }
+                    let is_phrase = quoted.is_some() && tokens.len() > 1;
+                    match (negated, is_phrase) {
+                        (false, true) => parsed.phrases.push(tokens),
+                        (true, true) => parsed.excluded_phrases.push(tokens),
+                        (false, false) => parsed.terms.extend(tokens),
+                        (true, false) => parsed.excluded_terms.extend(tokens),
+                    }
+                }
+            }
+        }
+
+        parsed.terms.extend(parsed.phrases.iter().flatten().cloned());
+        let mut seen = Vec::new();
+        parsed.terms.retain(|term| {
+            if seen.contains(term) {
+                false
+            } else {
+                seen.push(term.clone());
+                true
+            }
+        });
+        parsed
+    }
+
+    fn has_filters(&self) -> bool {
+        !self.sections.is_empty()
+            || !self.titles.is_empty()
+            || !self.categories.is_empty()
+            || !self.excluded_sections.is_empty()
+            || !self.excluded_titles.is_empty()
+            || !self.excluded_categories.is_empty()
+            || self.code != CodeFilter::Any
+    }
+
+    fn matches(&self, index: &SearchIndex, doc: &IndexedDocument) -> bool {
+        let in_section = |section: &String| {
+            doc.section.to_lowercase() == *section || doc.section.to_lowercase().starts_with(&format!("{}/", section))
+        };
+        if !self.sections.is_empty() && !self.sections.iter().any(in_section) {
+            return false;
+        }
+        if self.excluded_sections.iter().any(in_section) {
+            return false;
+        }
+
+        let in_category = |category: &String| doc.category.as_ref() == Some(category);
+        if !self.categories.is_empty() && !self.categories.iter().any(in_category) {
+            return false;
+        }
+        if self.excluded_categories.iter().any(in_category) {
+            return false;
+        }
+
+        let title = doc.title.to_lowercase();
+        if !self.titles.iter().all(|part| title.contains(part.as_str())) {
+            return false;
+        }
+        if self.excluded_titles.iter().any(|part| title.contains(part.as_str())) {
+            return false;
+        }
+
+        if self.excluded_terms.iter().any(|term| {
+            index.postings.get(term).is_some_and(|posting| posting.contains_key(&doc.url))
+        }) {
+            return false;
+        }
+
+        if self.code == CodeFilter::Only && !doc.spans.iter().any(|span| span.kind == SpanKind::Code) {
+            return false;
+        }
+
+        if !self.phrases.is_empty() || !self.excluded_phrases.is_empty() {
+            // Empty tokens keep phrases from running across span boundaries.
+            let doc_tokens: Vec<String> = tokenize(&doc.title)
+                .chain(
+                    doc.spans
+                        .iter()
+                        .filter(|span| self.code.allows(span.kind))
+                        .flat_map(|span| std::iter::once(String::new()).chain(tokenize(&span.text))),
+                )
+                .collect();
+            if !self.phrases.iter().all(|phrase| contains_sequence(&doc_tokens, phrase)) {
+                return false;
+            }
+            if self.excluded_phrases.iter().any(|phrase| contains_sequence(&doc_tokens, phrase)) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+fn contains_sequence(haystack: &[String], needle: &[String]) -> bool {
+    !needle.is_empty() && haystack.windows(needle.len()).any(|window| window == needle)
+}
+
+/// Ranks documents against `query` with BM25, best match first.
+///
+/// Besides plain words the query understands `"exact phrases"`, `-excluded` words and
+/// phrases, `in:section/path`, `title:word`, `category:name` and `is:code`; filters can be
+/// negated too. `total` counts every hit, but only the page picked by `options` is returned.
+pub fn search_content(query: &str, options: SearchOptions) -> SearchPage {
+    search_index(&SEARCH_INDEX.read().unwrap(), query, options)
+}
+
+/// `search_content` against `index` rather than the global search index.
+pub fn search_index(index: &SearchIndex, query: &str, options: SearchOptions) -> SearchPage {
+    let started = Instant::now();
     let query = query.trim();
-    
+    let (total, results) = rank_documents(index, query, &options);
+
+    SearchPage {
+        query: query.to_string(),
+        total,
+        took_ms: started.elapsed().as_secs_f64() * 1000.0,
+        offset: options.offset,
+        limit: options.limit,
+        results,
+    }
+}
+
+// Scores and filters every document, but only cuts snippets for the requested page.
+fn rank_documents(index: &SearchIndex, query: &str, options: &SearchOptions) -> (usize, Vec<SearchResult>) {
     if query.is_empty() {
-        return Vec::new();
+        return (0, Vec::new());
     }
     
-    let lowercase_query = query.to_lowercase();
-    let index = SEARCH_INDEX.read().unwrap();
+    let parsed = ParsedQuery::parse(query);
+    if parsed.terms.is_empty() && !parsed.has_filters() {
+        return (0, Vec::new());
+    }
+
+    let groups: Vec<Vec<(String, f32)>> = parsed.terms.iter().map(|term| index.expand(term)).collect();
+    // With nothing to rank by, every document passing the filters is a hit.
+    let scores = if parsed.terms.is_empty() {
+        index.documents.keys().map(|url| (url.as_str(), 0.0)).collect()
+    } else {
+        index.score(&groups, parsed.code)
+    };
     
-    index.par_iter()
-        .filter_map(|doc| {
-            if doc.lowercase_content.contains(&lowercase_query) {
-                let contexts = extract_all_contexts(&doc.content, &doc.lowercase_content, &doc.url, &lowercase_query);
-                
-                if !contexts.is_empty() {
-                    Some(SearchResult {
-                        title: doc.title.clone(),
-                        url: doc.url.clone(),
-                        contexts,
-                    })
-                } else {
-                    None
-                }
-            } else {
-                None
+    let mut hits: Vec<(&IndexedDocument, f32)> = scores
+        .par_iter()
+        .filter_map(|(url, score)| {
+            let doc = index.documents.get(*url)?;
+            parsed.matches(index, doc).then_some((doc, *score))
+        })
+        .collect();
+
+    hits.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.title.cmp(&b.0.title)));
+    let total = hits.len();
+    let start = options.offset.min(total);
+    let end = start.saturating_add(options.limit).min(total);
+
+    let results = hits[start..end]
+        .par_iter()
+        .map(|(doc, score)| {
+            let matched_terms = groups.iter().flatten().filter_map(|(term, _)| {
+                let posting = index.postings.get(term)?;
+                posting.contains_key(&doc.url).then(|| vec![term.clone()])
+            });
+            let needles: Vec<Vec<String>> = parsed.phrases.iter().cloned().chain(matched_terms).collect();
+            let mut contexts: Vec<ContextMatch> = doc
+                .spans
+                .iter()
+                .filter(|span| parsed.code.allows(span.kind))
+                .flat_map(|span| extract_snippets(span, &doc.url, &needles, options.window))
+                .collect();
+            let total_contexts = contexts.len();
+            contexts.truncate(options.max_contexts);
+
+            SearchResult {
+                title: doc.title.clone(),
+                url: doc.url.clone(),
+                score: *score,
+                contexts,
+                total_contexts,
             }
         })
+        .collect();
+
+    (total, results)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Suggestion {
+    pub text: String,
+    pub url: String,
+    pub kind: &'static str,
+}
+
+/// Title and heading completions for a partially typed query.
+pub fn suggest(query: &str) -> Vec<Suggestion> {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return Vec::new();
+    }
+    let last_word = tokenize(&query).last().unwrap_or_default();
+    let max_distance = max_typos(&last_word);
+
+    // Lower ranks first: whole text starts with the query, then one of its words does,
+    // then one of its words is a near miss for the last query word.
+    let rank = |text: &str| -> Option<u8> {
+        let lowercase = text.to_lowercase();
+        if lowercase.starts_with(&query) {
+            return Some(0);
+        }
+        if tokenize(&lowercase).any(|word| word.starts_with(&last_word)) && !last_word.is_empty() {
+            return Some(1);
+        }
+        if max_distance > 0
+            && tokenize(&lowercase).any(|word| {
+                let prefix: String = word.chars().take(last_word.chars().count()).collect();
+                edit_distance(&last_word, &prefix, max_distance).is_some()
+            })
+        {
+            return Some(2);
+        }
+        None
+    };
+
+    let index = SEARCH_INDEX.read().unwrap();
+    let mut ranked: Vec<(u8, u8, Suggestion)> = Vec::new();
+    for doc in index.documents.values() {
+        if let Some(r) = rank(&doc.title) {
+            ranked.push((r, 0, Suggestion {
+                text: doc.title.clone(),
+                url: doc.url.clone(),
+                kind: "title",
+            }));
+        }
+        for span in doc.spans.iter().filter(|span| span.kind == SpanKind::Heading) {
+            if let Some(r) = rank(&span.text) {
+                ranked.push((r, 1, Suggestion {
+                    text: span.text.clone(),
+                    url: match &span.heading {
+                        Some(slug) => format!("{}#{}", doc.url, slug),
+                        None => doc.url.clone(),
+                    },
+                    kind: "heading",
+                }));
+            }
+        }
+    }
+
+    ranked.sort_by(|a, b| {
+        (a.0, a.1, a.2.text.len(), &a.2.text).cmp(&(b.0, b.1, b.2.text.len(), &b.2.text))
+    });
+    ranked
+        .into_iter()
+        .map(|(_, _, suggestion)| suggestion)
+        .take(MAX_SUGGESTIONS)
         .collect()
 }
 
-fn extract_all_contexts(content: &str, lowercase_content: &str, base_url: &str, query: &str) -> Vec<ContextMatch> {
-    let mut contexts = Vec::new();
-    let mut last_pos = 0;
-    let heading_regex = regex::Regex::new(r"^(#{1,4})\s+(.+)$").unwrap(); // Match #, ##, ###, or #### headings
-    
-    while let Some(pos) = lowercase_content[last_pos..].find(query) {
-        let abs_pos = last_pos + pos;
-        
-        let mut last_heading = None;
-        for line in content[..abs_pos].lines().rev() {
-            if let Some(caps) = heading_regex.captures(line) {
-                let heading_text = caps.get(2).unwrap().as_str().trim();
-                last_heading = Some(
-                    heading_text
-                        .to_lowercase()
-                        .replace(' ', "-")
-                        .chars()
-                        .filter(|c| c.is_alphanumeric() || *c == '-')
-                        .collect::<String>()
-                );
-                break;
-            }
-        }
-        
-        let context_size = 40;
-        
-        let start_pos = lowercase_content[..abs_pos]
-            .rfind(|c| c == '.' || c == '!' || c == '?')
-            .map(|p| p + 1)
-            .unwrap_or_else(|| abs_pos.saturating_sub(context_size));
-        
-        let end_pos = abs_pos + query.len() + 
-            lowercase_content[abs_pos + query.len()..]
-                .find(|c| c == '.' || c == '!' || c == '?')
-                .unwrap_or_else(|| context_size.min(lowercase_content.len() - abs_pos - query.len()));
-        
-        let result = content[start_pos..end_pos].trim().to_string();
-        
-        let context_url = match last_heading {
-            Some(heading) => format!("{}#{}", base_url, heading),
-            None => base_url.to_string(),
-        };
-        
-        contexts.push(ContextMatch {
-            context: result,
-            url: context_url,
-        });
-        
-        last_pos = abs_pos + query.len() + 1;
-        if last_pos >= lowercase_content.len() {
-            break;
+// Character range of a snippet and the hits inside it.
+struct Snippet {
+    from: usize,
+    to: usize,
+    hits: Vec<(usize, usize)>,
+}
+
+/// Cuts snippets out of `span` around every occurrence of one of `needles` (term sequences).
+///
+/// Everything works on characters rather than bytes, since lowercasing can change the byte
+/// length of non-ASCII text. Hits whose windows overlap share a snippet.
+fn extract_snippets(span: &TextSpan, base_url: &str, needles: &[Vec<String>], window: usize) -> Vec<ContextMatch> {
+    let chars: Vec<char> = span.text.chars().collect();
+    let byte_offsets: Vec<usize> = span.text.char_indices().map(|(offset, _)| offset).collect();
+    let char_index = |byte: usize| byte_offsets.partition_point(|&offset| offset < byte);
+
+    // (start, end, lowercased term) for every token, in characters.
+    let tokens: Vec<(usize, usize, String)> = WORD_REGEX
+        .find_iter(&span.text)
+        .map(|token| (char_index(token.start()), char_index(token.end()), token.as_str().to_lowercase()))
+        .collect();
+
+    let mut hits: Vec<(usize, usize)> = Vec::new();
+    for i in 0..tokens.len() {
+        for needle in needles.iter().filter(|needle| !needle.is_empty()) {
+            let Some(candidate) = tokens.get(i..i + needle.len()) else {
+                continue;
+            };
+            if candidate.iter().zip(needle).all(|((_, _, term), word)| term == word) {
+                hits.push((candidate[0].0, candidate[needle.len() - 1].1));
+            }
         }
     }
-    
-    contexts
-}
\ No newline at end of file
+    if hits.is_empty() {
+        return Vec::new();
+    }
+
+    hits.sort_unstable();
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in hits {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    // Group hits whose windows touch into one snippet each.
+    let mut snippets: Vec<Snippet> = Vec::new();
+    for (start, end) in merged {
+        let from = start.saturating_sub(window);
+        let to = (end + window).min(chars.len());
+        match snippets.last_mut() {
+            Some(last) if from <= last.to => {
+                last.to = to;
+                last.hits.push((start, end));
+            }
+            _ => snippets.push(Snippet { from, to, hits: vec![(start, end)] }),
+        }
+    }
+
+    let url = match &span.heading {
+        Some(heading) => format!("{}#{}", base_url, heading),
+        None => base_url.to_string(),
+    };
+
+    snippets
+        .into_iter()
+        .map(|Snippet { mut from, mut to, hits }| {
+            let first = hits[0].0;
+            let last = hits[hits.len() - 1].1;
+
+            // Avoid cutting words in half at either end of the window.
+            if from > 0
+                && let Some(space) = chars[from..first].iter().position(|c| c.is_whitespace())
+            {
+                from += space;
+            }
+            if to < chars.len()
+                && let Some(space) = chars[last..to].iter().rposition(|c| c.is_whitespace())
+            {
+                to = last + space;
+            }
+            while from < first && chars[from].is_whitespace() {
+                from += 1;
+            }
+            while to > last && chars[to - 1].is_whitespace() {
+                to -= 1;
+            }
+
+            let mut html = String::new();
+            let mut cursor = from;
+            for &(start, end) in &hits {
+                html.push_str(&htmlescape::encode_minimal(&chars[cursor..start].iter().collect::<String>()));
+                html.push_str("<mark>");
+                html.push_str(&htmlescape::encode_minimal(&chars[start..end].iter().collect::<String>()));
+                html.push_str("</mark>");
+                cursor = end;
+            }
+            html.push_str(&htmlescape::encode_minimal(&chars[cursor..to].iter().collect::<String>()));
+
+            ContextMatch {
+                context: chars[from..to].iter().collect(),
+                html,
+                matches: hits
+                    .iter()
+                    .map(|&(start, end)| MatchRange { start: start - from, end: end - from })
+                    .collect(),
+                url: url.clone(),
+            }
+        })
+        .collect()
+}
@@ -51,7 +51,7 @@ async fn parse_tweet_data(json: Value) -> Option<TweetData> {
 
     let has_media = data
         .get("photos")
-        .map_or(false, |p| !p.as_array().unwrap_or(&vec![]).is_empty());
+        .is_some_and(|p| !p.as_array().unwrap_or(&vec![]).is_empty());
     let media_url = if has_media {
         data.get("photos")?
             .get(0)?
@@ -103,14 +103,12 @@ async fn load_profile_image(url: &str, username: &str) -> Option<DynamicImage> {
 
     let response = client.get(url).send().await;
 
-    if let Ok(resp) = response {
-        if resp.status().is_success() {
-            if let Ok(bytes) = resp.bytes().await {
-                if let Ok(img) = image::load_from_memory(&bytes) {
-                    return Some(img);
-                }
-            }
-        }
+    if let Ok(resp) = response
+        && resp.status().is_success()
+        && let Ok(bytes) = resp.bytes().await
+        && let Ok(img) = image::load_from_memory(&bytes)
+    {
+        return Some(img);
     }
 
     let fallback_url = format!("https://wisp.rex.wf/x/{}", username);
@@ -0,0 +1,95 @@
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use crate::cache::MARKDOWN_CACHE;
+use crate::content::{relative_url, CONTENT_INDEX};
+use crate::linkcheck::recheck_links;
+use crate::search::refresh_documents;
+
+// Editors tend to write a file in several steps, so changes are batched for a short while.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Watches `base_path` and keeps the content index, the search index and the markdown cache
+/// in sync with it, re-checking internal links after every change. The returned watcher
+/// must be kept alive for as long as updates are wanted.
+pub fn watch_content(base_path: &Path) -> notify::Result<RecommendedWatcher> {
+    let (tx, rx) = channel::<notify::Result<Event>>();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(base_path, RecursiveMode::Recursive)?;
+
+    let base_path = base_path.to_path_buf();
+    let canonical_base = base_path.canonicalize()?;
+
+    thread::spawn(move || {
+        while let Ok(first) = rx.recv() {
+            let mut changed = BTreeSet::new();
+            collect_paths(first, &mut changed);
+            loop {
+                match rx.recv_timeout(DEBOUNCE) {
+                    Ok(event) => collect_paths(event, &mut changed),
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+
+            for path in changed {
+                let relative = path
+                    .strip_prefix(&canonical_base)
+                    .or_else(|_| path.strip_prefix(&base_path))
+                    .map(Path::to_path_buf);
+                if let Ok(relative) = relative
+                    && !relative.as_os_str().is_empty()
+                {
+                    apply_change(&relative, &base_path.join(&relative));
+                }
+            }
+            recheck_links();
+        }
+    });
+
+    Ok(watcher)
+}
+
+fn collect_paths(event: notify::Result<Event>, changed: &mut BTreeSet<PathBuf>) {
+    match event {
+        Ok(event) if !event.kind.is_access() => changed.extend(event.paths),
+        Ok(_) => {}
+        Err(e) => eprintln!("Content watcher error: {}", e),
+    }
+}
+
+fn apply_change(relative: &Path, full_path: &Path) {
+    if relative
+        .components()
+        .any(|c| c.as_os_str().to_string_lossy().starts_with('.'))
+    {
+        return;
+    }
+
+    if full_path.is_file() && relative.extension().is_none_or(|ext| ext != "md") {
+        return;
+    }
+
+    let url = relative_url(relative);
+
+    let links_changed = {
+        let mut content = CONTENT_INDEX.write().unwrap();
+        let before = content.link_targets();
+        content.refresh(relative);
+        content.link_targets() != before
+    };
+
+    // Pages added, removed or renamed can break or fix wikilinks anywhere else.
+    let mut cache = MARKDOWN_CACHE.lock().unwrap();
+    if links_changed {
+        cache.clear();
+    } else {
+        cache.invalidate(&full_path.to_string_lossy());
+    }
+    drop(cache);
+    refresh_documents(&url);
+}
@@ -0,0 +1,149 @@
+// Search for static exports, where there is no server to answer /api/search. Loads the
+// exported /search-index.json and ranks it the way the server does for plain terms: BM25
+// with title and heading boosts, prefix matches counting for less, `-word` excluding pages.
+// Phrases and `in:`/`title:`/`category:`/`is:` filters need the server.
+(function () {
+    const K1 = 1.2;
+    const B = 0.75;
+    const TITLE_BOOST = 4;
+    const HEADING_BOOST = 2;
+    const PREFIX_WEIGHT = 0.7;
+    const MIN_PREFIX_LEN = 3;
+    const MAX_PREFIX_EXPANSIONS = 10;
+    const PAGE_SIZE = 10;
+    const MAX_CONTEXTS = 3;
+    const WINDOW = 40;
+    const WORD = /[\p{Alphabetic}\p{M}\p{Nd}]+/gu;
+
+    const tokenize = (text) => (text.match(WORD) || []).map((t) => t.toLowerCase());
+
+    const escapeHtml = (text) =>
+        text.replace(/[&<>"']/g, (c) => ({ '&': '&amp;', '<': '&lt;', '>': '&gt;', '"': '&quot;', "'": '&#39;' })[c]);
+
+    function expand(index, terms, term) {
+        const expansions = [];
+        if (index.postings[term]) expansions.push([term, 1]);
+        if ([...term].length >= MIN_PREFIX_LEN) {
+            for (const candidate of terms) {
+                if (expansions.length > MAX_PREFIX_EXPANSIONS) break;
+                if (candidate !== term && candidate.startsWith(term)) expansions.push([candidate, PREFIX_WEIGHT]);
+            }
+        }
+        return expansions;
+    }
+
+    function rank(index, terms, query) {
+        const words = query.split(/\s+/).filter(Boolean);
+        const include = words.filter((w) => !w.startsWith('-')).flatMap(tokenize);
+        const exclude = words.filter((w) => w.startsWith('-')).flatMap((w) => tokenize(w.slice(1)));
+        const documents = Object.values(index.documents);
+        const averageLength = Math.max(index.total_length / Math.max(documents.length, 1), 1);
+
+        const scores = new Map();
+        const matched = new Map();
+        for (const term of include) {
+            const best = new Map();
+            for (const [expansion, weight] of expand(index, terms, term)) {
+                const posting = index.postings[expansion];
+                const count = Object.keys(posting).length;
+                const idf = Math.log(1 + (documents.length - count + 0.5) / (count + 0.5));
+                for (const [url, f] of Object.entries(posting)) {
+                    const doc = index.documents[url];
+                    if (!doc) continue;
+                    const tf = (f.body || 0) + (f.code || 0) + TITLE_BOOST * (f.title || 0) + HEADING_BOOST * (f.heading || 0);
+                    const norm = 1 - B + (B * doc.length) / averageLength;
+                    const score = (weight * idf * tf * (K1 + 1)) / (tf + K1 * norm);
+                    best.set(url, Math.max(best.get(url) || 0, score));
+                    if (!matched.has(url)) matched.set(url, new Set());
+                    matched.get(url).add(expansion);
+                }
+            }
+            for (const [url, score] of best) scores.set(url, (scores.get(url) || 0) + score);
+        }
+
+        return [...scores]
+            .filter(([url]) => !exclude.some((term) => index.postings[term] && index.postings[term][url]))
+            .map(([url, score]) => ({ doc: index.documents[url], score, terms: matched.get(url) }))
+            .sort((a, b) => b.score - a.score || a.doc.title.localeCompare(b.doc.title));
+    }
+
+    // Up to MAX_CONTEXTS snippets of WINDOW characters either side of a hit, hits in <mark>.
+    function snippets(doc, terms) {
+        const contexts = [];
+        for (const span of doc.spans) {
+            for (const match of span.text.matchAll(WORD)) {
+                if (!terms.has(match[0].toLowerCase())) continue;
+                const start = Math.max(match.index - WINDOW, 0);
+                const end = Math.min(match.index + match[0].length + WINDOW, span.text.length);
+                const html =
+                    (start > 0 ? '…' : '') +
+                    escapeHtml(span.text.slice(start, match.index)) +
+                    '<mark>' + escapeHtml(match[0]) + '</mark>' +
+                    escapeHtml(span.text.slice(match.index + match[0].length, end)) +
+                    (end < span.text.length ? '…' : '');
+                contexts.push({ html, url: span.heading ? doc.url + '#' + span.heading : doc.url });
+                break;
+            }
+        }
+        return contexts;
+    }
+
+    function render(container, query, page, hits) {
+        if (hits.length === 0) {
+            container.innerHTML =
+                '<div class="p-4 bg-neutral-200 text-sm font-mono text-neutral-600 dark:text-neutral-400 dark:bg-neutral-800 my-4">' +
+                '<p>No results found for "' + escapeHtml(query) + '".</p></div>';
+            return;
+        }
+        const pages = Math.ceil(hits.length / PAGE_SIZE);
+        page = Math.min(Math.max(page, 1), pages);
+        const first = (page - 1) * PAGE_SIZE;
+        const shown = hits.slice(first, first + PAGE_SIZE);
+        const link = (p) => '/search?q=' + encodeURIComponent(query) + '&page=' + p;
+
+        let html = '<div class="my-4"><p class="text-neutral-600 dark:text-neutral-400 mb-4">Found results in ' +
+            hits.length + ' page' + (hits.length !== 1 ? 's' : '') + ' for "' + escapeHtml(query) + '"' +
+            (pages > 1 ? ', showing ' + (first + 1) + '&ndash;' + (first + shown.length) : '') + '</p>';
+        for (const hit of shown) {
+            const contexts = snippets(hit.doc, hit.terms);
+            html += '<div class="mb-6"><h3 class="font-semibold text-normal mb-2"><a href="/' + hit.doc.url + '">' +
+                escapeHtml(hit.doc.title) + '</a></h3>';
+            for (const context of contexts.slice(0, MAX_CONTEXTS)) {
+                html += '<a href="/' + context.url + '" class="mt-2 p-2 border-[1px] hover:bg-neutral-100 block dark:hover:bg-neutral-800 border-neutral-400 dark:border-neutral-600 mb-2">' +
+                    '<p class="search-snippet whitespace-pre-line text-sm text-neutral-700 dark:text-neutral-300">' + context.html + '</p></a>';
+            }
+            const hidden = contexts.length - MAX_CONTEXTS;
+            if (hidden > 0) {
+                html += '<a href="/' + hit.doc.url + '" class="text-sm font-mono text-neutral-500">+' + hidden +
+                    ' more match' + (hidden !== 1 ? 'es' : '') + '</a>';
+            }
+            html += '</div>';
+        }
+        if (pages > 1) {
+            html += '<div class="flex justify-between items-center text-sm font-mono mt-4">' +
+                (page > 1 ? '<a href="' + link(page - 1) + '">&larr; prev</a>' : '<span></span>') +
+                '<span class="text-neutral-500">page ' + page + ' of ' + pages + '</span>' +
+                (page < pages ? '<a href="' + link(page + 1) + '">next &rarr;</a>' : '<span></span>') +
+                '</div>';
+        }
+        container.innerHTML = html + '</div>';
+    }
+
+    document.addEventListener('DOMContentLoaded', async () => {
+        const container = document.getElementById('search-results');
+        const params = new URLSearchParams(window.location.search);
+        const query = (params.get('q') || '').trim();
+        if (!container || !query) return;
+
+        document.querySelector('input[name="q"]').value = query;
+        document.title = 'search - ' + query;
+        try {
+            const res = await fetch('/search-index.json');
+            const { index } = await res.json();
+            const terms = Object.keys(index.postings).sort();
+            render(container, query, parseInt(params.get('page'), 10) || 1, rank(index, terms, query));
+        } catch (e) {
+            container.textContent = 'Search is unavailable: the index could not be loaded.';
+        }
+    });
+})();